@@ -10,6 +10,8 @@ const WEIGHT: u32 = 0x20u;
 const JOINT: u32 = 0x40u;
 const COLOR: u32 = 0x80u;
 
+const NO_JOINTS: u32 = 0xffffffffu;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) _padding_0: f32,
@@ -29,32 +31,52 @@ struct InstanceInput {
     @location(12) transform_column_1: vec4<f32>,
     @location(13) transform_column_2: vec4<f32>,
     @location(14) transform_column_3: vec4<f32>,
+    @location(15) joint_offset: u32,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>,
-    @location(1) color: vec4<f32>,
-    @location(2) @interpolate(flat) shader_kinds: u32,
+    @location(0) tex_coords_0: vec2<f32>,
+    @location(1) tex_coords_1: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) @interpolate(flat) shader_kinds: u32,
 };
 
 @group(0) @binding(0)
 var<uniform> camera: mat4x4<f32>;
 
+@group(2) @binding(0)
+var<storage, read> joint_matrices: array<mat4x4<f32>>;
+
+fn skin_matrix(model: VertexInput, joint_offset: u32) -> mat4x4<f32> {
+    let joints = joint_offset + model.joints;
+
+    return model.weights.x * joint_matrices[joints.x]
+        + model.weights.y * joint_matrices[joints.y]
+        + model.weights.z * joint_matrices[joints.z]
+        + model.weights.w * joint_matrices[joints.w];
+}
+
 @vertex
 fn vs_main(
     model: VertexInput,
     instance: InstanceInput,
 ) -> VertexOutput {
-    let transform = mat4x4<f32>(
+    var transform = mat4x4<f32>(
         instance.transform_column_0,
         instance.transform_column_1,
         instance.transform_column_2,
         instance.transform_column_3,
     );
 
+    let skinned = (model.shader_kinds & (JOINT | WEIGHT)) == (JOINT | WEIGHT);
+    if skinned && instance.joint_offset != NO_JOINTS {
+        transform = transform * skin_matrix(model, instance.joint_offset);
+    }
+
     var out: VertexOutput;
-    out.tex_coords = model.tex_coords_0;
+    out.tex_coords_0 = model.tex_coords_0;
+    out.tex_coords_1 = model.tex_coords_1;
     out.color = model.color;
     out.shader_kinds = model.shader_kinds;
     out.clip_position = camera * transform * vec4<f32>(model.position, 1.0);
@@ -68,9 +90,26 @@ var t_diffuse: texture_2d<f32>;
 @group(1) @binding(1)
 var s_diffuse: sampler;
 
+struct TextureUniform {
+    transform: mat3x3<f32>,
+    tex_coord: u32,
+};
+
+@group(1) @binding(2)
+var<uniform> diffuse_info: TextureUniform;
+
+// UV set a texture samples with, after its KHR_texture_transform
+fn tex_coords(in: VertexOutput, texture: TextureUniform) -> vec2<f32> {
+    var tex_coords = in.tex_coords_0;
+    if texture.tex_coord == 1u {
+        tex_coords = in.tex_coords_1;
+    }
+    return (texture.transform * vec3<f32>(tex_coords, 1.0)).xy;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let texture_sample = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let texture_sample = textureSample(t_diffuse, s_diffuse, tex_coords(in, diffuse_info));
     if (in.shader_kinds & COLOR) != 0u {
         return in.color;
     }
@@ -0,0 +1,361 @@
+use std::{fmt, path::PathBuf};
+
+/// Viewer options, from the command line natively and from the URL query
+/// parameters on the web, both using the same option names
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Models loaded at startup
+    pub models: Vec<PathBuf>,
+    /// Scene shown by the models that have it instead of their default one
+    pub scene: Option<usize>,
+    /// Clip played by the models that have it instead of their first one
+    pub animation: Option<String>,
+    /// Camera start, the default one when unset
+    pub camera_position: Option<glam::Vec3>,
+    pub window_size: Option<(u32, u32)>,
+    pub fill_color: wgpu::Color,
+    /// First mode supported by the surface when unset
+    pub present_mode: Option<wgpu::PresentMode>,
+    /// Directory caching the parsed models, natively only, no cache when
+    /// unset
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            models: vec![PathBuf::from("assets/CesiumMilkTruck.glb")],
+            scene: None,
+            animation: None,
+            camera_position: None,
+            window_size: None,
+            fill_color: wgpu::Color {
+                r: 9f64 / 255f64,
+                g: 46f64 / 255f64,
+                b: 32f64 / 255f64,
+                a: 1.0,
+            },
+            present_mode: None,
+            cache_dir: None,
+        }
+    }
+}
+
+/// Why the options couldn't be parsed
+#[derive(Debug, Clone)]
+pub enum ConfigError {
+    /// `--help` was passed
+    Help,
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ConfigError::*;
+
+        match self {
+            Help => write!(f, "help requested"),
+            UnknownOption(option) => write!(f, "unknown option {option:?}"),
+            MissingValue(option) => write!(f, "missing value for {option:?}"),
+            InvalidValue {
+                option,
+                value,
+                expected,
+            } => write!(f, "invalid {option} {value:?}, expected {expected}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub const USAGE: &'static str = "\
+Usage: mario_skurt [OPTIONS] [MODEL]...
+
+Arguments:
+  [MODEL]...                  glTF or GLB files to load [default: assets/CesiumMilkTruck.glb]
+
+Options:
+  --scene <INDEX>             Scene shown by the models that have it
+  --animation <NAME>          Animation clip played by the models that have it
+  --camera <X,Y,Z>            Initial camera position
+  --size <WIDTHxHEIGHT>       Window size
+  --fill-color <RRGGBB>       Background color, in hexadecimal
+  --present-mode <MODE>       fifo, fifo-relaxed, mailbox, immediate, auto-vsync or auto-no-vsync
+  --cache-dir <DIR>           Directory caching the parsed models, not used on the web
+  --no-cache                  Don't cache the parsed models [default]
+  -h, --help                  Print this help
+
+On the web, the same options are read from the URL query parameters, models
+being given with `model`: ?model=assets/Fox.glb&animation=Run";
+
+    /// Parses the command line arguments, without the program name
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ConfigError> {
+        let mut options = Vec::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                return Err(ConfigError::Help);
+            }
+
+            let Some(option) = arg.strip_prefix("--") else {
+                options.push(("model".to_owned(), arg));
+                continue;
+            };
+
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name.to_owned(), value.to_owned()),
+                // Flags take no value, as on the web where they're given alone
+                None if FLAGS.contains(&option) => (option.to_owned(), String::new()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| ConfigError::MissingValue(option.to_owned()))?;
+                    (option.to_owned(), value)
+                }
+            };
+            options.push((name, value));
+        }
+
+        Self::from_options(options)
+    }
+
+    /// Parses the query parameters of the page URL
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_url() -> Result<Self, ConfigError> {
+        let search = web_sys::window()
+            .and_then(|window| window.location().search().ok())
+            .unwrap_or_default();
+
+        let options = (search.trim_start_matches('?').split('&'))
+            .filter(|parameter| !parameter.is_empty())
+            .map(|parameter| {
+                let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+                let decode = |text: &str| {
+                    let text = text.replace('+', " ");
+                    let decoded = urlencoding::decode(&text).map(|decoded| decoded.into_owned());
+                    decoded.unwrap_or(text)
+                };
+                (decode(name), decode(value))
+            })
+            .collect::<Vec<_>>();
+
+        Self::from_options(options)
+    }
+
+    /// Options given more than once take the last value, except models which
+    /// all replace the default ones
+    fn from_options(options: Vec<(String, String)>) -> Result<Self, ConfigError> {
+        let mut config = Self {
+            models: Vec::new(),
+            ..Self::default()
+        };
+
+        for (name, value) in options {
+            config.set(&name, value)?;
+        }
+
+        if config.models.is_empty() {
+            config.models = Self::default().models;
+        }
+
+        Ok(config)
+    }
+
+    fn set(&mut self, name: &str, value: String) -> Result<(), ConfigError> {
+        let invalid = |expected| ConfigError::InvalidValue {
+            option: name.to_owned(),
+            value: value.clone(),
+            expected,
+        };
+
+        match name {
+            "model" => self.models.push(PathBuf::from(&value)),
+            "scene" => {
+                let scene = value.parse().map_err(|_| invalid("a scene index"))?;
+                self.scene = Some(scene);
+            }
+            "animation" => self.animation = Some(value),
+            "camera" => {
+                let position = parse_floats::<3>(&value).ok_or_else(|| invalid("X,Y,Z"))?;
+                self.camera_position = Some(glam::Vec3::from_array(position));
+            }
+            "size" => {
+                let size = (value.split_once('x'))
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                    .filter(|&(width, height)| width > 0 && height > 0)
+                    .ok_or_else(|| invalid("WIDTHxHEIGHT"))?;
+                self.window_size = Some(size);
+            }
+            "fill-color" => {
+                self.fill_color = parse_color(&value).ok_or_else(|| invalid("RRGGBB"))?
+            }
+            "present-mode" => {
+                let present_mode = match value.as_str() {
+                    "fifo" => wgpu::PresentMode::Fifo,
+                    "fifo-relaxed" => wgpu::PresentMode::FifoRelaxed,
+                    "mailbox" => wgpu::PresentMode::Mailbox,
+                    "immediate" => wgpu::PresentMode::Immediate,
+                    "auto-vsync" => wgpu::PresentMode::AutoVsync,
+                    "auto-no-vsync" => wgpu::PresentMode::AutoNoVsync,
+                    _ => return Err(invalid("a present mode")),
+                };
+                self.present_mode = Some(present_mode);
+            }
+            "cache-dir" => self.cache_dir = Some(PathBuf::from(&value)),
+            "no-cache" if value.is_empty() => self.cache_dir = None,
+            "no-cache" => return Err(invalid("no value")),
+            _ => return Err(ConfigError::UnknownOption(name.to_owned())),
+        }
+
+        Ok(())
+    }
+}
+
+/// Options without a value
+const FLAGS: [&str; 1] = ["no-cache"];
+
+/// Comma separated numbers, `N` of them
+fn parse_floats<const N: usize>(text: &str) -> Option<[f32; N]> {
+    let mut values = [0f32; N];
+    let mut parts = text.split(',');
+
+    for value in &mut values {
+        *value = parts.next()?.trim().parse().ok()?;
+    }
+
+    parts.next().is_none().then_some(values)
+}
+
+/// Opaque color written `RRGGBB`, with an optional `#`
+fn parse_color(text: &str) -> Option<wgpu::Color> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    let channel = |shift: u32| f64::from((rgb >> shift) & 0xff) / 255f64;
+
+    Some(wgpu::Color {
+        r: channel(16),
+        g: channel(8),
+        b: channel(0),
+        a: 1.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn models_replace_the_default_one() {
+        assert_eq!(parse(&[]).unwrap().models, Config::default().models);
+
+        let config = parse(&["assets/Fox.glb", "--scene", "1", "assets/Box.glb"]).unwrap();
+        assert_eq!(
+            config.models,
+            [
+                PathBuf::from("assets/Fox.glb"),
+                PathBuf::from("assets/Box.glb")
+            ]
+        );
+    }
+
+    #[test]
+    fn values_follow_an_equal_sign_or_come_next() {
+        let config = parse(&["--scene=2", "--animation", "Run"]).unwrap();
+        assert_eq!(config.scene, Some(2));
+        assert_eq!(config.animation.as_deref(), Some("Run"));
+
+        // The last value given wins
+        let config = parse(&["--scene", "2", "--scene=3"]).unwrap();
+        assert_eq!(config.scene, Some(3));
+    }
+
+    #[test]
+    fn missing_values_and_unknown_options_are_errors() {
+        assert!(matches!(
+            parse(&["--scene"]),
+            Err(ConfigError::MissingValue(option)) if option == "scene"
+        ));
+        assert!(matches!(
+            parse(&["--speed=2"]),
+            Err(ConfigError::UnknownOption(option)) if option == "speed"
+        ));
+        assert!(matches!(parse(&["-h"]), Err(ConfigError::Help)));
+    }
+
+    #[test]
+    fn sizes_are_positive() {
+        assert_eq!(
+            parse(&["--size", "800x600"]).unwrap().window_size,
+            Some((800, 600))
+        );
+
+        for size in ["0x10", "10x0", "800", "800x600x2", "-1x10"] {
+            assert!(
+                matches!(
+                    parse(&["--size", size]),
+                    Err(ConfigError::InvalidValue { .. })
+                ),
+                "{size}"
+            );
+        }
+    }
+
+    #[test]
+    fn camera_takes_three_coordinates() {
+        // Negative values aren't taken for options
+        let config = parse(&["--camera", "-3,0.5,-3"]).unwrap();
+        assert_eq!(
+            config.camera_position,
+            Some(glam::Vec3::new(-3.0, 0.5, -3.0))
+        );
+
+        for camera in ["1,2", "1,2,3,4", "1,a,3"] {
+            assert!(parse(&["--camera", camera]).is_err(), "{camera}");
+        }
+    }
+
+    #[test]
+    fn fill_colors_are_hexadecimal() {
+        let color = parse(&["--fill-color", "#FF8000"]).unwrap().fill_color;
+        assert_eq!(
+            (color.r, color.g, color.b, color.a),
+            (1.0, 128.0 / 255.0, 0.0, 1.0)
+        );
+        assert_eq!(parse(&["--fill-color=ff8000"]).unwrap().fill_color, color);
+
+        for color in ["#FF80", "FF800G", "#+F8000"] {
+            assert!(parse(&["--fill-color", color]).is_err(), "{color}");
+        }
+    }
+
+    #[test]
+    fn cache_is_opt_in() {
+        assert_eq!(parse(&[]).unwrap().cache_dir, None);
+
+        let config = parse(&["--cache-dir", "cache", "assets/Fox.glb"]).unwrap();
+        assert_eq!(config.cache_dir, Some(PathBuf::from("cache")));
+        assert_eq!(config.models, [PathBuf::from("assets/Fox.glb")]);
+
+        // Flags don't take the next argument as their value
+        let config = parse(&["--cache-dir=cache", "--no-cache", "assets/Fox.glb"]).unwrap();
+        assert_eq!(config.cache_dir, None);
+        assert_eq!(config.models, [PathBuf::from("assets/Fox.glb")]);
+        assert!(parse(&["--no-cache=yes"]).is_err());
+    }
+}
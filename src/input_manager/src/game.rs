@@ -6,6 +6,10 @@ impl crate::InputManager {
             || self.mouse_state.get(&action).map(KeyState::to_value) == Some(1f32)
     }
 
+    fn consume_action_press(&mut self, action: UserAction) -> bool {
+        self.just_pressed.remove(&action)
+    }
+
     fn get_action_value(&self, action: UserAction) -> f32 {
         self.key_state.get(&action).map_or(0f32, KeyState::to_value)
     }
@@ -57,4 +61,77 @@ impl crate::InputManager {
     pub fn right_click_pressed(&self) -> bool {
         self.is_action_pressed(UserAction::RightClick)
     }
+
+    #[must_use]
+    pub fn consume_next_scene(&mut self) -> bool {
+        self.consume_action_press(UserAction::NextScene)
+    }
+
+    #[must_use]
+    pub fn consume_next_camera(&mut self) -> bool {
+        self.consume_action_press(UserAction::NextCamera)
+    }
+
+    #[must_use]
+    pub fn consume_print_scene_tree(&mut self) -> bool {
+        self.consume_action_press(UserAction::PrintSceneTree)
+    }
+
+    #[must_use]
+    pub fn consume_place_models(&mut self) -> bool {
+        self.consume_action_press(UserAction::PlaceModels)
+    }
+
+    #[must_use]
+    pub fn consume_toggle_animation(&mut self) -> bool {
+        self.consume_action_press(UserAction::ToggleAnimation)
+    }
+
+    #[must_use]
+    pub fn consume_stop_animation(&mut self) -> bool {
+        self.consume_action_press(UserAction::StopAnimation)
+    }
+
+    #[must_use]
+    pub fn consume_next_animation(&mut self) -> bool {
+        self.consume_action_press(UserAction::NextAnimation)
+    }
+
+    #[must_use]
+    pub fn consume_cycle_playback_mode(&mut self) -> bool {
+        self.consume_action_press(UserAction::CyclePlaybackMode)
+    }
+
+    /// Returns the speed factor requested since the last call
+    #[must_use]
+    pub fn consume_animation_speed_factor(&mut self) -> f32 {
+        let mut factor = 1f32;
+        if self.consume_action_press(UserAction::SpeedUpAnimation) {
+            factor *= 2f32;
+        }
+        if self.consume_action_press(UserAction::SlowDownAnimation) {
+            factor /= 2f32;
+        }
+
+        factor
+    }
+
+    /// Returns -1, 0 or 1 frame steps requested since the last call
+    #[must_use]
+    pub fn consume_animation_step(&mut self) -> f32 {
+        let forward = self.consume_action_press(UserAction::StepAnimationForward);
+        let backward = self.consume_action_press(UserAction::StepAnimationBackward);
+
+        f32::from(u8::from(forward)) - f32::from(u8::from(backward))
+    }
+
+    #[must_use]
+    pub fn consume_toggle_blend_layer(&mut self) -> bool {
+        self.consume_action_press(UserAction::ToggleBlendLayer)
+    }
+
+    #[must_use]
+    pub fn consume_toggle_additive_layer(&mut self) -> bool {
+        self.consume_action_press(UserAction::ToggleAdditiveLayer)
+    }
 }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use winit::{
     dpi::PhysicalPosition,
@@ -18,6 +18,23 @@ enum UserAction {
 
     Escape,
 
+    NextScene,
+    NextCamera,
+    PrintSceneTree,
+    PlaceModels,
+
+    // Animation
+    ToggleAnimation,
+    StopAnimation,
+    NextAnimation,
+    CyclePlaybackMode,
+    SpeedUpAnimation,
+    SlowDownAnimation,
+    StepAnimationForward,
+    StepAnimationBackward,
+    ToggleBlendLayer,
+    ToggleAdditiveLayer,
+
     // Mouse
     LeftClick,
     RightClick,
@@ -72,6 +89,9 @@ pub struct InputManager {
     key_state: HashMap<UserAction, KeyState>,
     #[serde(skip)]
     mouse_state: HashMap<UserAction, KeyState>,
+    /// Actions pressed since they were last consumed, key repeats excluded
+    #[serde(skip)]
+    just_pressed: HashSet<UserAction>,
 }
 
 impl InputManager {
@@ -101,6 +121,11 @@ impl InputManager {
         let virtual_keycode = virtual_keycode.unwrap();
 
         if let Some(&key_action) = self.key_settings.get(&virtual_keycode) {
+            let was_pressed = matches!(self.key_state.get(&key_action), Some(KeyState::Pressed));
+            if state == ElementState::Pressed && !was_pressed {
+                self.just_pressed.insert(key_action);
+            }
+
             self.key_state.insert(key_action, state.into());
         }
     }
@@ -140,6 +165,7 @@ impl InputManager {
     pub fn clear_state(&mut self) {
         self.key_state.clear();
         self.mouse_state.clear();
+        self.just_pressed.clear();
 
         self.mouse_delta = (0f64, 0f64);
     }
@@ -226,6 +252,20 @@ impl Default for InputManager {
         key_settings.insert(VirtualKeyCode::Space, UserAction::Up);
         key_settings.insert(VirtualKeyCode::LShift, UserAction::Down);
         key_settings.insert(VirtualKeyCode::Escape, UserAction::Escape);
+        key_settings.insert(VirtualKeyCode::Tab, UserAction::NextScene);
+        key_settings.insert(VirtualKeyCode::C, UserAction::NextCamera);
+        key_settings.insert(VirtualKeyCode::I, UserAction::PrintSceneTree);
+        key_settings.insert(VirtualKeyCode::E, UserAction::PlaceModels);
+        key_settings.insert(VirtualKeyCode::P, UserAction::ToggleAnimation);
+        key_settings.insert(VirtualKeyCode::O, UserAction::StopAnimation);
+        key_settings.insert(VirtualKeyCode::N, UserAction::NextAnimation);
+        key_settings.insert(VirtualKeyCode::M, UserAction::CyclePlaybackMode);
+        key_settings.insert(VirtualKeyCode::PageUp, UserAction::SpeedUpAnimation);
+        key_settings.insert(VirtualKeyCode::PageDown, UserAction::SlowDownAnimation);
+        key_settings.insert(VirtualKeyCode::Period, UserAction::StepAnimationForward);
+        key_settings.insert(VirtualKeyCode::Comma, UserAction::StepAnimationBackward);
+        key_settings.insert(VirtualKeyCode::K, UserAction::ToggleBlendLayer);
+        key_settings.insert(VirtualKeyCode::L, UserAction::ToggleAdditiveLayer);
 
         let mut mouse_settings: HashMap<MouseButton, UserAction> = HashMap::new();
 
@@ -243,6 +283,7 @@ impl Default for InputManager {
             mouse_delta: (0.0, 0.0),
             key_state: HashMap::new(),
             mouse_state: HashMap::new(),
+            just_pressed: HashSet::new(),
         }
     }
 }
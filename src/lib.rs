@@ -1,5 +1,6 @@
 use render::DrawingContext;
 use winit::{
+    dpi::PhysicalSize,
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
     window::WindowBuilder,
@@ -8,9 +9,13 @@ use winit::{
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+mod config;
 mod render;
 pub mod utils;
 
+pub use config::{Config, ConfigError};
+pub use render::{Extras, Model, Node, NodeIndex};
+
 fn event_handler(
     event: &Event<()>,
     _event_loop_window_target: &EventLoopWindowTarget<()>,
@@ -91,11 +96,11 @@ fn init_log() {
 }
 
 #[cfg(target_arch = "wasm32")]
-fn init_window(window: &winit::window::Window) {
+fn init_window(window: &winit::window::Window, size: Option<(u32, u32)>) {
     // Winit prevents sizing with CSS, so we have to set
     // the size manually when on web.
-    use winit::dpi::PhysicalSize;
-    window.set_inner_size(PhysicalSize::new(600, 400));
+    let (width, height) = size.unwrap_or((600, 400));
+    window.set_inner_size(PhysicalSize::new(width, height));
 
     use winit::platform::web::WindowExtWebSys;
     web_sys::window()
@@ -109,24 +114,38 @@ fn init_window(window: &winit::window::Window) {
         .expect("Couldn't append canvas to document body.");
 }
 
+/// Web entry point, configured from the URL query parameters
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(start)]
+pub async fn start() {
+    init_log();
+
+    let config = Config::from_url().unwrap_or_else(|error| {
+        log::error!("Invalid URL parameters, using the defaults: {}", error);
+        Config::default()
+    });
+
+    run(config).await;
+}
+
 /// Run the application
 ///
 /// # Panics
 ///
 /// - If the application fails to initialize.
 /// - If the application fails to load the scene.
-#[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
-pub async fn run() {
-    #[cfg(target_arch = "wasm32")]
-    init_log();
-
+pub async fn run(config: Config) {
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let mut window_builder = WindowBuilder::new();
+    if let Some((width, height)) = config.window_size {
+        window_builder = window_builder.with_inner_size(PhysicalSize::new(width, height));
+    }
+    let window = window_builder.build(&event_loop).unwrap();
 
     #[cfg(target_arch = "wasm32")]
-    init_window(&window);
+    init_window(&window, config.window_size);
 
-    let mut drawing_context = DrawingContext::new(window).await;
+    let mut drawing_context = DrawingContext::new(window, config).await;
 
     event_loop.run(move |event, event_loop_window_target, control_flow| {
         event_handler(
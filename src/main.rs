@@ -8,8 +8,22 @@ fn init_log() {
 }
 
 fn main() {
+    use mario_skurt::{Config, ConfigError};
+
     #[cfg(not(target_arch = "wasm32"))]
     init_log();
 
-    pollster::block_on(mario_skurt::run());
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(ConfigError::Help) => {
+            println!("{}", Config::USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, Config::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    pollster::block_on(mario_skurt::run(config));
 }
@@ -1,6 +1,11 @@
 use std::collections::HashSet;
 
-use crate::render::asset_store::NodeIndex;
+use crate::render::asset_store::{
+    cache::{write_pod_slice, Cached, Reader},
+    error::{to_u32, ModelError},
+    pose::Pose,
+    NodeIndex,
+};
 
 #[derive(Clone, Copy, Debug)]
 pub enum PropertyKind {
@@ -10,23 +15,12 @@ pub enum PropertyKind {
     MorphTargetWeights,
 }
 
-impl PropertyKind {
-    #[inline]
-    fn size(&self) -> usize {
-        match self {
-            PropertyKind::Translation => 3,
-            PropertyKind::Rotation => 4,
-            PropertyKind::Scale => 3,
-            PropertyKind::MorphTargetWeights => 1,
-        }
-    }
-}
-
 pub enum PropertyValue {
     Translation(glam::Vec3),
     Rotation(glam::Quat),
     Scale(glam::Vec3),
-    MorphTargetWeights(f32),
+    /// One weight per morph target of the animated node mesh
+    MorphTargetWeights(Vec<f32>),
 }
 
 impl PropertyValue {
@@ -46,7 +40,7 @@ impl PropertyValue {
             PropertyKind::Translation => PropertyValue::Translation(glam::Vec3::from_slice(data)),
             PropertyKind::Scale => PropertyValue::Scale(glam::Vec3::from_slice(data)),
             PropertyKind::Rotation => PropertyValue::Rotation(glam::Quat::from_slice(data)),
-            PropertyKind::MorphTargetWeights => unimplemented!(),
+            PropertyKind::MorphTargetWeights => PropertyValue::MorphTargetWeights(data.to_vec()),
         }
     }
 }
@@ -83,13 +77,35 @@ impl From<gltf::animation::Interpolation> for Interpolation {
 }
 
 #[derive(Clone)]
-struct Data(Vec<f32>);
+struct Data {
+    values: Vec<f32>,
+    /// Number of floats per value, morph target weights have one per target
+    value_size: usize,
+    /// Cubic spline keyframes are stored as (in-tangent, value, out-tangent)
+    /// triplets instead of a single value
+    cubic_spline: bool,
+}
 
 impl Data {
-    fn get(&self, index: usize, property_kind: PropertyKind) -> &[f32] {
-        let start_index = index * property_kind.size();
-        let end_index = start_index + property_kind.size();
-        &self.0[start_index..end_index]
+    fn element(&self, index: usize, element: usize) -> &[f32] {
+        let elements_per_keyframe = if self.cubic_spline { 3 } else { 1 };
+        let start_index = (index * elements_per_keyframe + element) * self.value_size;
+        let end_index = start_index + self.value_size;
+        &self.values[start_index..end_index]
+    }
+
+    fn get(&self, index: usize) -> &[f32] {
+        self.element(index, usize::from(self.cubic_spline))
+    }
+
+    fn in_tangent(&self, index: usize) -> &[f32] {
+        debug_assert!(self.cubic_spline);
+        self.element(index, 0)
+    }
+
+    fn out_tangent(&self, index: usize) -> &[f32] {
+        debug_assert!(self.cubic_spline);
+        self.element(index, 2)
     }
 }
 
@@ -107,25 +123,57 @@ pub struct Channel {
 }
 
 impl Channel {
-    fn parse(channel: &gltf::animation::Channel, buffers: &[gltf::buffer::Data]) -> Self {
+    /// `channel_index` is the index of the channel in its animation
+    fn parse(
+        channel: &gltf::animation::Channel,
+        channel_index: usize,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Self, ModelError> {
         let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
 
         let target = channel.target();
         let property: PropertyKind = target.property().into();
-        let node_index = target.node().index();
-        let node_index = u32::try_from(node_index).expect("Node index overflow");
-        let node_index = NodeIndex(node_index);
+        let node_index = NodeIndex(to_u32(target.node().index(), "node index")?);
 
         let sampler = channel.sampler();
         let interpolation: Interpolation = sampler.interpolation().into();
 
+        let error = |accessor: &gltf::Accessor, reason| ModelError::InvalidAnimation {
+            animation: channel.animation().index(),
+            channel: channel_index,
+            accessor: Some(accessor.index()),
+            reason,
+        };
+
         let input_normalize = sampler.input().normalized();
         let output_normalize = sampler.output().normalized();
-        let times = read_times(&reader, input_normalize);
-        let data = read_outputs(&reader, output_normalize);
+        let times = read_times(&reader, input_normalize)
+            .ok_or_else(|| error(&sampler.input(), "unreadable keyframe times"))?;
+        let values = read_outputs(&reader, output_normalize)
+            .ok_or_else(|| error(&sampler.output(), "unreadable keyframe values"))?;
+        let Some(&duration) = times.last() else {
+            return Err(error(&sampler.input(), "no keyframe"));
+        };
+        if !strictly_increasing(&times) {
+            return Err(error(&sampler.input(), "keyframe times not increasing"));
+        }
+
+        let cubic_spline = interpolation == Interpolation::CubicSpline;
+        let elements_per_keyframe = if cubic_spline { 3 } else { 1 };
+        let value_size = values.len() / (times.len() * elements_per_keyframe);
 
-        // Already checked in read_times & outputs
-        let duration = *times.last().unwrap();
+        let expected_value_size = match property {
+            PropertyKind::Translation | PropertyKind::Scale => 3,
+            PropertyKind::Rotation => 4,
+            // One weight per morph target
+            PropertyKind::MorphTargetWeights => value_size.max(1),
+        };
+        if values.len() != times.len() * elements_per_keyframe * expected_value_size {
+            return Err(error(
+                &sampler.output(),
+                "keyframe value count doesn't match the keyframe times",
+            ));
+        }
 
         #[cfg(feature = "debug_gltf")]
         log::info!(
@@ -136,28 +184,38 @@ impl Channel {
             target.node().name().unwrap_or("None"),
         );
 
-        Channel {
+        Ok(Channel {
             node_index,
             interpolation,
             times,
-            data: Data(data),
+            data: Data {
+                values,
+                value_size,
+                cubic_spline,
+            },
             duration,
             property,
-        }
+        })
     }
 
-    pub fn interpolate(&self, time_since_program_start: f32) -> PropertyValue {
-        let time_mod = time_since_program_start % self.duration;
-
+    /// Samples the channel at `time` seconds since the start of its clip
+    pub fn interpolate(&self, time: f32) -> PropertyValue {
         use std::cmp::Ordering::Equal;
         let time_index = self
             .times
-            .binary_search_by(|a| a.partial_cmp(&time_mod).unwrap_or(Equal));
+            .binary_search_by(|a| a.partial_cmp(&time).unwrap_or(Equal));
 
         let Err(time_index) = time_index else {
-            let data = self.data.get(time_index.unwrap(), self.property);
+            let data = self.data.get(time_index.unwrap());
             return PropertyValue::from(self.property, data);
         };
+
+        // Before the first or after the last keyframe, hold the closest value
+        if time_index == 0 || time_index == self.times.len() {
+            let closest_index = time_index.min(self.times.len() - 1);
+            let data = self.data.get(closest_index);
+            return PropertyValue::from(self.property, data);
+        }
         let time_index = time_index - 1;
 
         #[cfg(debug_assertions)]
@@ -170,19 +228,21 @@ impl Channel {
         #[cfg(debug_assertions)]
         assert!(delta >= 0.0);
 
-        let time_ratio = (time_mod - first_time) / delta;
+        let time_ratio = (time - first_time) / delta;
 
-        // We are using linear interpolation for Translate & Scale
+        // We are using linear interpolation for Translate, Scale & Weights
         // We are using spherical linear interpolation for Rotation
+        // Cubic spline is a Hermite spline on every component, rotations
+        // being normalized afterwards
         // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#appendix-c-interpolation
         match self.interpolation {
             Interpolation::Step => {
-                let data = self.data.get(time_index, self.property);
+                let data = self.data.get(time_index);
                 PropertyValue::from(self.property, data)
             }
             Interpolation::Linear => {
-                let first = self.data.get(time_index, self.property);
-                let second = self.data.get(time_index + 1, self.property);
+                let first = self.data.get(time_index);
+                let second = self.data.get(time_index + 1);
 
                 match self.property {
                     PropertyKind::Scale | PropertyKind::Translation => {
@@ -199,24 +259,67 @@ impl Channel {
                         let data = &first.slerp(second, time_ratio).to_array()[..];
                         PropertyValue::from(self.property, data)
                     }
-                    PropertyKind::MorphTargetWeights => unimplemented!(),
+                    PropertyKind::MorphTargetWeights => {
+                        let data = first
+                            .iter()
+                            .zip(second)
+                            .map(|(first, second)| first + (second - first) * time_ratio)
+                            .collect();
+                        PropertyValue::MorphTargetWeights(data)
+                    }
+                }
+            }
+            Interpolation::CubicSpline => {
+                let t = time_ratio;
+                let t2 = t * t;
+                let t3 = t2 * t;
+
+                // Hermite basis, tangents are scaled by the keyframe delta
+                let value_factor = 2.0 * t3 - 3.0 * t2 + 1.0;
+                let out_tangent_factor = (t3 - 2.0 * t2 + t) * delta;
+                let next_value_factor = -2.0 * t3 + 3.0 * t2;
+                let in_tangent_factor = (t3 - t2) * delta;
+
+                let value = self.data.get(time_index);
+                let out_tangent = self.data.out_tangent(time_index);
+                let next_value = self.data.get(time_index + 1);
+                let in_tangent = self.data.in_tangent(time_index + 1);
+
+                let data = (0..self.data.value_size)
+                    .map(|i| {
+                        value_factor * value[i]
+                            + out_tangent_factor * out_tangent[i]
+                            + next_value_factor * next_value[i]
+                            + in_tangent_factor * in_tangent[i]
+                    })
+                    .collect::<Vec<_>>();
+
+                match PropertyValue::from(self.property, &data) {
+                    PropertyValue::Rotation(rotation) => {
+                        PropertyValue::Rotation(rotation.normalize())
+                    }
+                    value => value,
                 }
             }
-            Interpolation::CubicSpline => todo!(),
         }
     }
 }
 
 pub struct Animation {
-    #[cfg(feature = "debug_gltf")]
     pub name: Option<String>,
 
     pub target_nodes: HashSet<NodeIndex>,
     pub channels: Vec<Channel>,
+    /// Duration of the longest channel, channels are all sampled on the same
+    /// clip time
+    pub duration: f32,
 }
 
 impl Animation {
-    pub fn parse(animation: &gltf::Animation, buffers: &[gltf::buffer::Data]) -> Self {
+    pub fn parse(
+        animation: &gltf::Animation,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Self, ModelError> {
         #[cfg(feature = "debug_gltf")]
         log::info!(
             "Animation {} with {} channels",
@@ -227,21 +330,138 @@ impl Animation {
         let mut target_nodes = HashSet::new();
         let mut channels = Vec::new();
 
-        for channel in animation.channels() {
-            let channel = Channel::parse(&channel, buffers);
+        for (channel_index, channel) in animation.channels().enumerate() {
+            let channel = Channel::parse(&channel, channel_index, buffers)?;
             target_nodes.insert(channel.node_index);
             channels.push(channel);
         }
 
-        Animation {
-            #[cfg(feature = "debug_gltf")]
+        let duration = channels
+            .iter()
+            .map(|channel| channel.duration)
+            .fold(0.0, f32::max);
+
+        Ok(Animation {
             name: animation.name().map(ToOwned::to_owned),
             channels,
             target_nodes,
+            duration,
+        })
+    }
+
+    /// Samples every channel at `time`, animated components replacing the
+    /// ones of `pose`
+    pub fn sample(&self, pose: &mut Pose, time: f32) {
+        for channel in &self.channels {
+            let index = usize::try_from(channel.node_index.0).expect("Node index overflow");
+            let transform = &mut pose.transforms[index];
+
+            match channel.interpolate(time) {
+                PropertyValue::Translation(translation) => transform.translation = translation,
+                PropertyValue::Rotation(rotation) => transform.rotation = rotation,
+                PropertyValue::Scale(scale) => transform.scale = scale,
+                PropertyValue::MorphTargetWeights(weights) => pose.weights[index] = weights,
+            }
         }
     }
 }
 
+impl Cached for PropertyKind {
+    fn write(&self, writer: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            PropertyKind::Translation => 0,
+            PropertyKind::Rotation => 1,
+            PropertyKind::Scale => 2,
+            PropertyKind::MorphTargetWeights => 3,
+        };
+        tag.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        match u8::read(reader)? {
+            0 => Some(PropertyKind::Translation),
+            1 => Some(PropertyKind::Rotation),
+            2 => Some(PropertyKind::Scale),
+            3 => Some(PropertyKind::MorphTargetWeights),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for Interpolation {
+    fn write(&self, writer: &mut Vec<u8>) {
+        (*self as u8).write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        match u8::read(reader)? {
+            1 => Some(Interpolation::Linear),
+            2 => Some(Interpolation::Step),
+            3 => Some(Interpolation::CubicSpline),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for Channel {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.node_index.write(writer);
+        self.interpolation.write(writer);
+        write_pod_slice(writer, &self.times);
+        self.duration.write(writer);
+        self.property.write(writer);
+        write_pod_slice(writer, &self.data.values);
+        self.data.value_size.write(writer);
+        self.data.cubic_spline.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        let channel = Self {
+            node_index: Cached::read(reader)?,
+            interpolation: Cached::read(reader)?,
+            times: reader.read_pod_vec()?,
+            duration: Cached::read(reader)?,
+            property: Cached::read(reader)?,
+            data: Data {
+                values: reader.read_pod_vec()?,
+                value_size: Cached::read(reader)?,
+                cubic_spline: Cached::read(reader)?,
+            },
+        };
+
+        // Sampling indexes the values by keyframe
+        let elements_per_keyframe = if channel.data.cubic_spline { 3 } else { 1 };
+        let value_count = channel.times.len() * elements_per_keyframe * channel.data.value_size;
+        let valid = !channel.times.is_empty()
+            && strictly_increasing(&channel.times)
+            && channel.data.values.len() == value_count;
+        valid.then_some(channel)
+    }
+}
+
+impl Cached for Animation {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.name.write(writer);
+        self.target_nodes.write(writer);
+        self.channels.write(writer);
+        self.duration.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            name: Cached::read(reader)?,
+            target_nodes: Cached::read(reader)?,
+            channels: Cached::read(reader)?,
+            duration: Cached::read(reader)?,
+        })
+    }
+}
+
+/// Finite times, each after the previous one, as sampling binary searches them
+fn strictly_increasing(times: &[f32]) -> bool {
+    times.iter().all(|time| time.is_finite()) && times.windows(2).all(|times| times[0] < times[1])
+}
+
 // From https://github.com/adrien-ben/gltf-viewer-rs/blob/eebdd3/crates/libs/model/src/animation.rs#L464-L508
 use gltf::animation::util::ReadOutputs;
 
@@ -249,16 +469,15 @@ fn read_times<'a, 's, F>(
     reader: &gltf::animation::Reader<'a, 's, F>,
     // TODO: Find how to normalize times
     input_normalized: bool,
-) -> Vec<f32>
+) -> Option<Vec<f32>>
 where
     F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
 {
     let times = reader
         .read_inputs()
-        .map(|times| times.collect::<Vec<_>>())
-        .expect("No times were found");
+        .map(|times| times.collect::<Vec<_>>())?;
 
-    if input_normalized {
+    let times = if input_normalized {
         let (min, max) = times
             .iter()
             .fold((f32::MAX, f32::MIN), |(min, max), &time| {
@@ -272,50 +491,151 @@ where
             .collect::<Vec<_>>()
     } else {
         times
-    }
+    };
+
+    Some(times)
 }
 
 fn read_outputs<'a, 's, F>(
     reader: &gltf::animation::Reader<'a, 's, F>,
     output_normalized: bool,
-) -> Vec<f32>
+) -> Option<Vec<f32>>
 where
     F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
 {
     use glam::Quat;
 
-    let outputs = reader
-        .read_outputs()
-        .map(|outputs| match outputs {
-            ReadOutputs::Rotations(rotations) => {
-                let mut rotations: Vec<_> = rotations.into_f32().map(Quat::from_array).collect();
+    let outputs = reader.read_outputs().map(|outputs| match outputs {
+        ReadOutputs::Rotations(rotations) => {
+            let mut rotations: Vec<_> = rotations.into_f32().map(Quat::from_array).collect();
 
-                if output_normalized {
-                    for data in &mut rotations {
-                        *data = data.normalize();
-                    }
+            if output_normalized {
+                for data in &mut rotations {
+                    *data = data.normalize();
                 }
-
-                let rotations: Vec<_> = rotations.iter().map(Quat::to_array).collect();
-                bytemuck::cast_vec(rotations)
             }
-            ReadOutputs::Scales(vec3) | ReadOutputs::Translations(vec3) => {
-                let mut data: Vec<_> = vec3.map(glam::Vec3::from_array).collect();
 
-                if output_normalized {
-                    for data in &mut data {
-                        *data = data.normalize();
-                    }
-                }
+            let rotations: Vec<_> = rotations.iter().map(Quat::to_array).collect();
+            bytemuck::cast_vec(rotations)
+        }
+        ReadOutputs::Scales(vec3) | ReadOutputs::Translations(vec3) => {
+            let mut data: Vec<_> = vec3.map(glam::Vec3::from_array).collect();
 
-                bytemuck::cast_vec(data)
-            }
-            ReadOutputs::MorphTargetWeights(_morph_target) => {
-                // morph_target.into_f32().collect::<Vec<_>>()
-                panic!("MorphTargetWeights not supported")
+            if output_normalized {
+                for data in &mut data {
+                    *data = data.normalize();
+                }
             }
-        })
-        .expect("No data was found");
+
+            bytemuck::cast_vec(data)
+        }
+        // Normalized weights are already converted by `into_f32`
+        ReadOutputs::MorphTargetWeights(weights) => weights.into_f32().collect(),
+    });
 
     outputs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Translation channel along x, `values` holding triplets for cubic
+    /// splines
+    fn channel(interpolation: Interpolation, times: &[f32], values: &[f32]) -> Channel {
+        Channel {
+            node_index: NodeIndex(0),
+            interpolation,
+            times: times.to_vec(),
+            duration: *times.last().unwrap(),
+            property: PropertyKind::Translation,
+            data: Data {
+                values: values.iter().flat_map(|&x| [x, 0.0, 0.0]).collect(),
+                value_size: 3,
+                cubic_spline: interpolation == Interpolation::CubicSpline,
+            },
+        }
+    }
+
+    fn sample_x(channel: &Channel, time: f32) -> f32 {
+        match channel.interpolate(time) {
+            PropertyValue::Translation(translation) => translation.x,
+            _ => panic!("Not a translation"),
+        }
+    }
+
+    #[test]
+    fn step_holds_the_previous_keyframe() {
+        let channel = channel(Interpolation::Step, &[0.0, 1.0, 2.0], &[1.0, 2.0, 3.0]);
+
+        assert_eq!(sample_x(&channel, -1.0), 1.0);
+        assert_eq!(sample_x(&channel, 0.0), 1.0);
+        assert_eq!(sample_x(&channel, 0.99), 1.0);
+        assert_eq!(sample_x(&channel, 1.0), 2.0);
+        assert_eq!(sample_x(&channel, 1.5), 2.0);
+        assert_eq!(sample_x(&channel, 3.0), 3.0);
+    }
+
+    #[test]
+    fn cubic_spline_with_flat_tangents_eases() {
+        // (in-tangent, value, out-tangent) per keyframe
+        let channel = channel(
+            Interpolation::CubicSpline,
+            &[0.0, 1.0],
+            &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        );
+
+        assert_eq!(sample_x(&channel, 0.0), 0.0);
+        assert!((sample_x(&channel, 0.25) - 0.15625).abs() < 1e-6);
+        assert!((sample_x(&channel, 0.5) - 0.5).abs() < 1e-6);
+        assert_eq!(sample_x(&channel, 1.0), 1.0);
+    }
+
+    #[test]
+    fn cubic_spline_tangents_are_scaled_by_the_keyframe_delta() {
+        // Tangents matching the slope of a straight line reproduce it
+        let slope = 0.5;
+        let channel = channel(
+            Interpolation::CubicSpline,
+            &[0.0, 2.0],
+            &[slope, 0.0, slope, slope, 1.0, slope],
+        );
+
+        for time in [0.2, 0.5, 1.0, 1.7] {
+            assert!((sample_x(&channel, time) - time * slope).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn keyframe_times_must_increase() {
+        assert!(strictly_increasing(&[0.0, 0.5, 1.0]));
+        assert!(strictly_increasing(&[1.0]));
+        assert!(!strictly_increasing(&[0.0, 1.0, 1.0]));
+        assert!(!strictly_increasing(&[0.0, 2.0, 1.0]));
+        assert!(!strictly_increasing(&[0.0, f32::NAN]));
+    }
+
+    #[test]
+    fn cubic_spline_rotations_are_normalized() {
+        let identity = [0.0, 0.0, 0.0, 1.0];
+        let half_turn = [0.0, 1.0, 0.0, 0.0];
+        let zero = [0.0; 4];
+        let channel = Channel {
+            node_index: NodeIndex(0),
+            interpolation: Interpolation::CubicSpline,
+            times: vec![0.0, 1.0],
+            duration: 1.0,
+            property: PropertyKind::Rotation,
+            data: Data {
+                values: [zero, identity, zero, zero, half_turn, zero].concat(),
+                value_size: 4,
+                cubic_spline: true,
+            },
+        };
+
+        match channel.interpolate(0.5) {
+            PropertyValue::Rotation(rotation) => assert!(rotation.is_normalized()),
+            _ => panic!("Not a rotation"),
+        }
+    }
+}
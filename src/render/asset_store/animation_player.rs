@@ -0,0 +1,357 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackMode {
+    /// Restarts from the beginning once the end is reached
+    Loop,
+    /// Holds the last frame once the end is reached
+    Once,
+    /// Plays forward then backward
+    PingPong,
+}
+
+impl PlaybackMode {
+    /// Maps an unwrapped play time to the time sampled in a clip
+    fn clip_time(self, time: f32, duration: f32) -> f32 {
+        if duration <= 0.0 {
+            return 0.0;
+        }
+
+        match self {
+            PlaybackMode::Once => time.clamp(0.0, duration),
+            PlaybackMode::Loop => time.rem_euclid(duration),
+            PlaybackMode::PingPong => {
+                let time = time.rem_euclid(2.0 * duration);
+                if time > duration {
+                    2.0 * duration - time
+                } else {
+                    time
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// How a layer combines with the pose below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerBlend {
+    /// Moves the pose towards the layer clip by the layer weight
+    Override,
+    /// Adds the layer clip difference to the rest pose on top of the pose
+    Additive,
+}
+
+/// Clip played on top of the active clip, always looping
+#[derive(Debug, Clone)]
+pub struct AnimationLayer {
+    pub clip: usize,
+    pub blend: LayerBlend,
+    pub weight: f32,
+    time: f32,
+}
+
+/// Clip being faded out after [AnimationPlayer::cross_fade]
+#[derive(Debug, Clone)]
+struct CrossFade {
+    clip: Option<usize>,
+    time: f32,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// Drives the active animation clip of a [Model](super::Model)
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    active_clip: Option<usize>,
+    state: PlaybackState,
+    mode: PlaybackMode,
+    speed: f32,
+    /// Time played since the start of the clip, scaled by `speed`. Not
+    /// wrapped, see [AnimationPlayer::clip_time] for the sampled time
+    time: f32,
+    /// Duration of the [PlaybackMode::Once] clip paused at its end, which
+    /// [AnimationPlayer::play] rewinds
+    finished: Option<f32>,
+
+    cross_fade: Option<CrossFade>,
+    layers: Vec<AnimationLayer>,
+}
+
+impl AnimationPlayer {
+    /// Plays the first clip in a loop, if there is one
+    pub fn new(clip_count: usize) -> Self {
+        Self {
+            active_clip: (clip_count > 0).then_some(0),
+            state: PlaybackState::Playing,
+            mode: PlaybackMode::Loop,
+            speed: 1.0,
+            time: 0.0,
+            finished: None,
+
+            cross_fade: None,
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn active_clip(&self) -> Option<usize> {
+        self.active_clip
+    }
+
+    /// Switches to another clip (or none) and restarts it
+    pub fn set_active_clip(&mut self, clip: Option<usize>) {
+        self.active_clip = clip;
+        self.time = 0.0;
+        self.finished = None;
+        self.cross_fade = None;
+    }
+
+    /// Switches to another clip (or none), blending from the current one
+    /// over `duration` seconds
+    pub fn cross_fade(&mut self, clip: Option<usize>, duration: f32) {
+        if duration <= 0.0 {
+            self.set_active_clip(clip);
+            return;
+        }
+
+        self.cross_fade = Some(CrossFade {
+            clip: self.active_clip,
+            time: self.time,
+            elapsed: 0.0,
+            duration,
+        });
+        self.active_clip = clip;
+        self.time = 0.0;
+        self.finished = None;
+    }
+
+    /// Clip being faded out, with its sampled time and the weight of the
+    /// active clip over it
+    pub fn fading_clip(&self, durations: &[f32]) -> Option<(Option<usize>, f32, f32)> {
+        let cross_fade = self.cross_fade.as_ref()?;
+
+        let duration = cross_fade.clip.map_or(0.0, |clip| durations[clip]);
+        let time = self.mode.clip_time(cross_fade.time, duration);
+        let factor = cross_fade.elapsed / cross_fade.duration;
+
+        Some((cross_fade.clip, time, factor))
+    }
+
+    pub fn layers(&self) -> &[AnimationLayer] {
+        &self.layers
+    }
+
+    /// Plays `clip` on top of the active one, returns the layer index
+    pub fn add_layer(&mut self, clip: usize, blend: LayerBlend, weight: f32) -> usize {
+        self.layers.push(AnimationLayer {
+            clip,
+            blend,
+            weight,
+            time: 0.0,
+        });
+
+        self.layers.len() - 1
+    }
+
+    pub fn clear_layers(&mut self) {
+        self.layers.clear();
+    }
+
+    /// Time at which the layer clip is sampled
+    pub fn layer_time(&self, layer: &AnimationLayer, duration: f32) -> f32 {
+        PlaybackMode::Loop.clip_time(layer.time, duration)
+    }
+
+    pub fn state(&self) -> PlaybackState {
+        self.state
+    }
+
+    /// Resumes the clip, from the start again if it played to its end, or
+    /// from its end when playing backward
+    pub fn play(&mut self) {
+        if let Some(duration) = self.finished.take() {
+            self.time = if self.speed < 0.0 { duration } else { 0.0 };
+        }
+        self.state = PlaybackState::Playing;
+    }
+
+    pub fn pause(&mut self) {
+        if self.state == PlaybackState::Playing {
+            self.state = PlaybackState::Paused;
+        }
+    }
+
+    /// Pauses and goes back to the start of the clip
+    pub fn stop(&mut self) {
+        self.state = PlaybackState::Stopped;
+        self.time = 0.0;
+        self.finished = None;
+        self.cross_fade = None;
+    }
+
+    pub fn mode(&self) -> PlaybackMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: PlaybackMode) {
+        self.mode = mode;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Negative speeds play the clip backward
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// Time played since the start of the clip, before wrapping
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Moves to `time` seconds in the clip, the playback state is kept
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+        self.finished = None;
+    }
+
+    /// `durations` holds the duration of every clip of the model
+    pub fn advance(&mut self, delta_time: f32, durations: &[f32]) {
+        if self.state != PlaybackState::Playing {
+            return;
+        }
+
+        let delta_time = delta_time * self.speed;
+        self.time += delta_time;
+
+        let duration = self.active_clip.map_or(0.0, |clip| durations[clip]);
+        if self.mode == PlaybackMode::Once && !(0.0..=duration).contains(&self.time) {
+            self.time = self.time.clamp(0.0, duration);
+            self.state = PlaybackState::Paused;
+            self.finished = Some(duration);
+        }
+
+        if let Some(cross_fade) = &mut self.cross_fade {
+            cross_fade.time += delta_time;
+            cross_fade.elapsed += delta_time.abs();
+
+            if cross_fade.elapsed >= cross_fade.duration {
+                self.cross_fade = None;
+            }
+        }
+
+        for layer in &mut self.layers {
+            layer.time += delta_time;
+        }
+    }
+
+    /// Time at which the active clip is sampled
+    pub fn clip_time(&self, duration: f32) -> f32 {
+        self.mode.clip_time(self.time, duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DURATIONS: &[f32] = &[2.0];
+
+    fn player(mode: PlaybackMode) -> AnimationPlayer {
+        let mut player = AnimationPlayer::new(DURATIONS.len());
+        player.set_mode(mode);
+        player
+    }
+
+    fn clip_time(player: &AnimationPlayer) -> f32 {
+        player.clip_time(DURATIONS[0])
+    }
+
+    #[test]
+    fn ping_pong_plays_backward_after_the_end() {
+        let mut player = player(PlaybackMode::PingPong);
+
+        player.advance(1.5, DURATIONS);
+        assert_eq!(clip_time(&player), 1.5);
+        player.advance(1.0, DURATIONS);
+        assert_eq!(clip_time(&player), 1.5);
+        player.advance(2.0, DURATIONS);
+        assert_eq!(clip_time(&player), 0.5);
+        assert_eq!(player.state(), PlaybackState::Playing);
+    }
+
+    #[test]
+    fn ping_pong_backward_speed_mirrors_from_the_start() {
+        let mut player = player(PlaybackMode::PingPong);
+        player.set_speed(-1.0);
+
+        player.advance(0.5, DURATIONS);
+        assert_eq!(clip_time(&player), 0.5);
+    }
+
+    #[test]
+    fn once_holds_the_last_frame_and_pauses() {
+        let mut player = player(PlaybackMode::Once);
+
+        player.advance(1.5, DURATIONS);
+        assert_eq!(player.state(), PlaybackState::Playing);
+        player.advance(1.0, DURATIONS);
+        assert_eq!(player.time(), 2.0);
+        assert_eq!(clip_time(&player), 2.0);
+        assert_eq!(player.state(), PlaybackState::Paused);
+
+        // Paused players don't move
+        player.advance(1.0, DURATIONS);
+        assert_eq!(player.time(), 2.0);
+    }
+
+    #[test]
+    fn once_backward_stops_at_the_start() {
+        let mut player = player(PlaybackMode::Once);
+        player.seek(0.5);
+        player.set_speed(-1.0);
+
+        player.advance(1.0, DURATIONS);
+        assert_eq!(player.time(), 0.0);
+        assert_eq!(player.state(), PlaybackState::Paused);
+    }
+
+    #[test]
+    fn play_restarts_finished_clips() {
+        let mut player = player(PlaybackMode::Once);
+        player.advance(3.0, DURATIONS);
+
+        player.play();
+        assert_eq!(player.time(), 0.0);
+        player.advance(1.0, DURATIONS);
+        assert_eq!(player.time(), 1.0);
+        assert_eq!(player.state(), PlaybackState::Playing);
+
+        // Backward clips restart from their end
+        player.set_speed(-1.0);
+        player.advance(2.0, DURATIONS);
+        assert_eq!(player.state(), PlaybackState::Paused);
+        player.play();
+        assert_eq!(player.time(), 2.0);
+
+        // Clips paused before their end resume where they were
+        player.advance(0.5, DURATIONS);
+        player.pause();
+        player.play();
+        assert_eq!(player.time(), 1.5);
+    }
+
+    #[test]
+    fn loop_wraps_around() {
+        let mut player = player(PlaybackMode::Loop);
+
+        player.advance(5.0, DURATIONS);
+        assert_eq!(clip_time(&player), 1.0);
+    }
+}
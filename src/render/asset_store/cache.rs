@@ -0,0 +1,690 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    path::{Path, PathBuf},
+};
+
+use crate::render::{
+    asset_store::{
+        import::{external_uris, load_relative_uri},
+        node_layout::NodeLayout,
+        ModelData, PerPrimitive, PerPrimitiveMetadata,
+    },
+    camera::Projection,
+};
+
+/// Bumped whenever the encoding of a cached type changes, so that older
+/// cache files are rebuilt instead of misread
+const FORMAT_VERSION: u32 = 3;
+const MAGIC: &[u8; 4] = b"MSKC";
+
+/// Little endian binary encoding of the data cached for a model, sequences
+/// being prefixed by their length
+pub(super) trait Cached: Sized {
+    fn write(&self, writer: &mut Vec<u8>);
+    /// `None` when `reader` is truncated or holds an invalid value
+    fn read(reader: &mut Reader) -> Option<Self>;
+}
+
+pub(super) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+
+        let (taken, bytes) = self.bytes.split_at(len);
+        self.bytes = bytes;
+        Some(taken)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    /// Reads a slice written by [write_pod_slice]
+    pub fn read_pod_vec<T: bytemuck::Pod>(&mut self) -> Option<Vec<T>> {
+        let len = usize::read(self)?;
+        let bytes = self.take(len.checked_mul(std::mem::size_of::<T>())?)?;
+        Some(bytemuck::pod_collect_to_vec(bytes))
+    }
+}
+
+/// Writes `slice` in one go, for the large vertex, index and pixel data
+pub(super) fn write_pod_slice<T: bytemuck::Pod>(writer: &mut Vec<u8>, slice: &[T]) {
+    slice.len().write(writer);
+    writer.extend_from_slice(bytemuck::cast_slice(slice));
+}
+
+macro_rules! cached_numbers {
+    ($($number:ty),*) => {
+        $(
+            impl Cached for $number {
+                fn write(&self, writer: &mut Vec<u8>) {
+                    writer.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read(reader: &mut Reader) -> Option<Self> {
+                    Some(Self::from_le_bytes(reader.take_array()?))
+                }
+            }
+        )*
+    };
+}
+
+cached_numbers!(u8, u32, u64, f32);
+
+impl Cached for usize {
+    fn write(&self, writer: &mut Vec<u8>) {
+        (*self as u64).write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        usize::try_from(u64::read(reader)?).ok()
+    }
+}
+
+impl Cached for bool {
+    fn write(&self, writer: &mut Vec<u8>) {
+        u8::from(*self).write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        match u8::read(reader)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for String {
+    fn write(&self, writer: &mut Vec<u8>) {
+        write_pod_slice(writer, self.as_bytes());
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        String::from_utf8(reader.read_pod_vec()?).ok()
+    }
+}
+
+impl<T: Cached> Cached for Option<T> {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.is_some().write(writer);
+        if let Some(value) = self {
+            value.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        match bool::read(reader)? {
+            true => Some(Some(T::read(reader)?)),
+            false => Some(None),
+        }
+    }
+}
+
+impl<T: Cached> Cached for Vec<T> {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.len().write(writer);
+        for value in self {
+            value.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        let len = usize::read(reader)?;
+        // A corrupted length can't allocate more than the file size
+        let max_len = reader.bytes.len() / std::mem::size_of::<T>().max(1);
+        let mut values = Vec::with_capacity(len.min(max_len));
+        for _ in 0..len {
+            values.push(T::read(reader)?);
+        }
+        Some(values)
+    }
+}
+
+impl<A: Cached, B: Cached> Cached for (A, B) {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.0.write(writer);
+        self.1.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some((A::read(reader)?, B::read(reader)?))
+    }
+}
+
+// Written in key order, so that the same model always gives the same bytes
+impl<K: Cached + Ord + Hash, V: Cached> Cached for HashMap<K, V> {
+    fn write(&self, writer: &mut Vec<u8>) {
+        let mut entries = self.iter().collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|&(key, _)| key);
+
+        entries.len().write(writer);
+        for (key, value) in entries {
+            key.write(writer);
+            value.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Vec::<(K, V)>::read(reader)?.into_iter().collect())
+    }
+}
+
+impl<T: Cached + Ord + Hash> Cached for HashSet<T> {
+    fn write(&self, writer: &mut Vec<u8>) {
+        let mut values = self.iter().collect::<Vec<_>>();
+        values.sort_unstable();
+
+        values.len().write(writer);
+        for value in values {
+            value.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Vec::<T>::read(reader)?.into_iter().collect())
+    }
+}
+
+impl<const N: usize> Cached for [f32; N] {
+    fn write(&self, writer: &mut Vec<u8>) {
+        for value in self {
+            value.write(writer);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        let mut values = [0f32; N];
+        for value in &mut values {
+            *value = f32::read(reader)?;
+        }
+        Some(values)
+    }
+}
+
+macro_rules! cached_glam {
+    ($($glam:ty: $len:literal),*) => {
+        $(
+            impl Cached for $glam {
+                fn write(&self, writer: &mut Vec<u8>) {
+                    self.to_array().write(writer);
+                }
+
+                fn read(reader: &mut Reader) -> Option<Self> {
+                    Some(Self::from_array(<[f32; $len]>::read(reader)?))
+                }
+            }
+        )*
+    };
+}
+
+cached_glam!(glam::Vec3: 3, glam::Quat: 4);
+
+impl Cached for glam::Mat3 {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.to_cols_array().write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self::from_cols_array(&<[f32; 9]>::read(reader)?))
+    }
+}
+
+impl Cached for glam::Mat4 {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.to_cols_array().write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self::from_cols_array(&<[f32; 16]>::read(reader)?))
+    }
+}
+
+/// Extras are kept as their JSON text
+impl Cached for serde_json::Value {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.to_string().write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        serde_json::from_str(&String::read(reader)?).ok()
+    }
+}
+
+impl Cached for wgpu::PrimitiveTopology {
+    fn write(&self, writer: &mut Vec<u8>) {
+        use wgpu::PrimitiveTopology::*;
+
+        let tag: u8 = match self {
+            PointList => 0,
+            LineList => 1,
+            LineStrip => 2,
+            TriangleList => 3,
+            TriangleStrip => 4,
+        };
+        tag.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        use wgpu::PrimitiveTopology::*;
+
+        match u8::read(reader)? {
+            0 => Some(PointList),
+            1 => Some(LineList),
+            2 => Some(LineStrip),
+            3 => Some(TriangleList),
+            4 => Some(TriangleStrip),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for Projection {
+    fn write(&self, writer: &mut Vec<u8>) {
+        match *self {
+            Projection::Perspective { yfov, znear, zfar } => {
+                0u8.write(writer);
+                yfov.write(writer);
+                znear.write(writer);
+                zfar.write(writer);
+            }
+            Projection::Orthographic { ymag, znear, zfar } => {
+                1u8.write(writer);
+                ymag.write(writer);
+                znear.write(writer);
+                zfar.write(writer);
+            }
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        match u8::read(reader)? {
+            0 => Some(Projection::Perspective {
+                yfov: f32::read(reader)?,
+                znear: f32::read(reader)?,
+                zfar: Option::read(reader)?,
+            }),
+            1 => Some(Projection::Orthographic {
+                ymag: f32::read(reader)?,
+                znear: f32::read(reader)?,
+                zfar: f32::read(reader)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "debug_gltf")]
+impl Cached for super::ModelMetadata {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.name.write(writer);
+        self.path.write(writer);
+        self.scene_count.write(writer);
+        self.mesh_count.write(writer);
+        self.texture_count.write(writer);
+        self.animation_count.write(writer);
+        self.skin_count.write(writer);
+        self.material_count.write(writer);
+        self.node_count.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            name: String::read(reader)?,
+            path: String::read(reader)?,
+            scene_count: usize::read(reader)?,
+            mesh_count: usize::read(reader)?,
+            texture_count: usize::read(reader)?,
+            animation_count: usize::read(reader)?,
+            skin_count: usize::read(reader)?,
+            material_count: usize::read(reader)?,
+            node_count: usize::read(reader)?,
+        })
+    }
+}
+
+impl Cached for PerPrimitive {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.metadata.mesh_name.write(writer);
+        self.id.write(writer);
+        self.index_range.write(writer);
+        self.vertex_range.write(writer);
+        write_pod_slice(writer, &self.instances);
+        self.staging_index.is_some().write(writer);
+        if let Some(indices) = &self.staging_index {
+            write_pod_slice(writer, indices);
+        }
+        write_pod_slice(writer, &self.staging_vertex);
+        self.topology.write(writer);
+        self.morph_targets.write(writer);
+        self.material.write(writer);
+        self.instance_node_indices.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            metadata: PerPrimitiveMetadata {
+                mesh_name: Option::read(reader)?,
+            },
+            id: usize::read(reader)?,
+            index_range: Cached::read(reader)?,
+            vertex_range: Cached::read(reader)?,
+            instances: reader.read_pod_vec()?,
+            staging_index: match bool::read(reader)? {
+                true => Some(reader.read_pod_vec()?),
+                false => None,
+            },
+            staging_vertex: reader.read_pod_vec()?,
+            topology: Cached::read(reader)?,
+            morph_targets: Cached::read(reader)?,
+            material: Cached::read(reader)?,
+            instance_node_indices: Cached::read(reader)?,
+        })
+    }
+}
+
+/// The packed vertices and indices aren't stored, being the concatenation
+/// of the ones of every primitive
+impl Cached for ModelData {
+    fn write(&self, writer: &mut Vec<u8>) {
+        #[cfg(feature = "debug_gltf")]
+        self.metadata.write(writer);
+        self.samplers.write(writer);
+        self.images.write(writer);
+
+        self.node_layout.write(writer);
+        self.default_scene.write(writer);
+        self.cameras.write(writer);
+        self.animations.write(writer);
+        self.skins.write(writer);
+        self.joint_count.write(writer);
+
+        self.per_primitives.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        #[cfg(feature = "debug_gltf")]
+        let metadata = Cached::read(reader)?;
+        let samplers = Cached::read(reader)?;
+        let images = Cached::read(reader)?;
+
+        let node_layout: NodeLayout = Cached::read(reader)?;
+        let default_scene = Cached::read(reader)?;
+        let cameras = Cached::read(reader)?;
+        let animations = Cached::read(reader)?;
+        let skins = Cached::read(reader)?;
+        let joint_count = Cached::read(reader)?;
+
+        let per_primitives = Vec::<PerPrimitive>::read(reader)?;
+
+        let mut global_indices = Vec::new();
+        let mut global_vertices = Vec::new();
+        for primitive in &per_primitives {
+            global_vertices.extend_from_slice(&primitive.staging_vertex);
+            if let Some(indices) = &primitive.staging_index {
+                global_indices.extend_from_slice(indices);
+            }
+        }
+
+        // Ranges and nodes are trusted when drawing and animating, so a
+        // damaged file must not point past the decoded data
+        let fits = |(start, end): (usize, usize), len| start <= end && end <= len;
+        let node_count = node_layout.nodes.len();
+        let in_bounds = per_primitives.iter().all(|primitive| {
+            fits(primitive.index_range, global_indices.len())
+                && fits(primitive.vertex_range, global_vertices.len())
+                && (primitive.instance_node_indices.iter())
+                    .all(|node_index| (node_index.0 as usize) < node_count)
+        });
+        if !in_bounds {
+            return None;
+        }
+
+        Some(Self {
+            // Set by the caller, the cache file doesn't depend on it
+            path: PathBuf::new(),
+            #[cfg(feature = "debug_gltf")]
+            metadata,
+            samplers,
+            images,
+
+            node_layout,
+            default_scene,
+            cameras,
+            animations,
+            skins,
+            joint_count,
+
+            per_primitives,
+            global_indices,
+            global_vertices,
+        })
+    }
+}
+
+/// Initial hash given to [fnv1a]
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// 64 bits FNV-1a, stable across builds unlike the standard hashers
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Hash of a model file and of the external buffers and images it
+/// references, `None` when one of them can't be read
+pub(super) async fn content_hash<P: AsRef<Path>>(path: P, bytes: &[u8]) -> Option<u64> {
+    let mut hash = fnv1a(OFFSET_BASIS, bytes);
+
+    let gltf = gltf::Gltf::from_slice(bytes).ok()?;
+    let base = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+
+    for uri in external_uris(&gltf) {
+        let external_bytes = load_relative_uri(base, uri).await.ok()?;
+        hash = fnv1a(hash, &external_bytes);
+    }
+
+    Some(hash)
+}
+
+/// On-disk cache of parsed models, one file per source file
+#[derive(Debug, Clone)]
+pub struct AssetCache {
+    dir: PathBuf,
+}
+
+impl AssetCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Readable name of the cache file of `source`, with a hash of its path
+    /// telling apart the paths sanitized to the same name
+    fn file_path(&self, source: &Path) -> PathBuf {
+        let source = source.to_string_lossy();
+        let name = (source.chars())
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let hash = fnv1a(OFFSET_BASIS, source.as_bytes());
+
+        self.dir.join(format!("{name}.{hash:016x}.bin"))
+    }
+
+    /// Model cached for `source`, if the cache file was written from the
+    /// same content hash with the current format
+    pub(super) fn read(&self, source: &Path, hash: u64) -> Option<ModelData> {
+        let bytes = std::fs::read(self.file_path(source)).ok()?;
+        let mut reader = Reader { bytes: &bytes };
+
+        let up_to_date = reader.take(MAGIC.len())? == MAGIC
+            && u32::read(&mut reader)? == FORMAT_VERSION
+            && bool::read(&mut reader)? == cfg!(feature = "debug_gltf")
+            && u64::read(&mut reader)? == hash;
+        if !up_to_date {
+            return None;
+        }
+
+        let data = ModelData::read(&mut reader);
+        if data.is_none() {
+            log::warn!("Corrupted cache file for {}", source.display());
+        }
+
+        Some(ModelData {
+            path: source.to_path_buf(),
+            ..data?
+        })
+    }
+
+    /// Replaces the cache file of `source`, logging failures as the model
+    /// can still be loaded without it
+    pub(super) fn write(&self, source: &Path, hash: u64, data: &ModelData) {
+        let mut writer = Vec::new();
+        writer.extend_from_slice(MAGIC);
+        FORMAT_VERSION.write(&mut writer);
+        // Metadata is only stored by debug builds
+        cfg!(feature = "debug_gltf").write(&mut writer);
+        hash.write(&mut writer);
+        data.write(&mut writer);
+
+        // Written aside then renamed, so that other workers never read a
+        // partial file
+        let path = self.file_path(source);
+        let partial_path = path.with_extension("partial");
+        let result = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&partial_path, &writer))
+            .and_then(|_| std::fs::rename(&partial_path, &path));
+
+        match result {
+            Ok(()) => {
+                #[cfg(feature = "debug_gltf")]
+                log::info!("Cached {} in {:?}", source.display(), path);
+            }
+            Err(error) => log::warn!("Failed to cache {}: {}", source.display(), error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(path: &str) -> ModelData {
+        pollster::block_on(ModelData::load(path, None)).expect("Could not load test model")
+    }
+
+    fn encode(data: &ModelData) -> Vec<u8> {
+        let mut writer = Vec::new();
+        data.write(&mut writer);
+        writer
+    }
+
+    /// Empty directory for the cache files of a test
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn models_roundtrip() {
+        for path in ["assets/BoxTextured.glb", "assets/Fox.glb"] {
+            let bytes = encode(&load(path));
+            let data = ModelData::read(&mut Reader { bytes: &bytes }).expect("Unreadable model");
+
+            let again = encode(&data);
+            let first = again.iter().zip(&bytes).position(|(a, b)| a != b);
+            assert_eq!((again.len(), first), (bytes.len(), None), "{path}");
+        }
+    }
+
+    #[test]
+    fn truncated_models_are_rejected() {
+        let bytes = encode(&load("assets/BoxTextured.glb"));
+
+        for len in [0, 1, bytes.len() / 2, bytes.len() - 1] {
+            let mut reader = Reader {
+                bytes: &bytes[..len],
+            };
+            assert!(ModelData::read(&mut reader).is_none(), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn out_of_bounds_primitives_are_rejected() {
+        let tamper = |tamper: fn(&mut PerPrimitive)| {
+            let mut data = load("assets/BoxTextured.glb");
+            tamper(&mut data.per_primitives[0]);
+            ModelData::read(&mut Reader {
+                bytes: &encode(&data),
+            })
+        };
+
+        assert!(tamper(|_| ()).is_some());
+        assert!(tamper(|primitive| primitive.index_range.1 += 1).is_none());
+        assert!(tamper(|primitive| primitive.vertex_range.0 += 100).is_none());
+        assert!(tamper(|primitive| primitive.instance_node_indices[0].0 = 7).is_none());
+    }
+
+    #[test]
+    fn corrupted_lengths_are_rejected() {
+        let mut bytes = Vec::new();
+        u64::MAX.write(&mut bytes);
+
+        assert!(Vec::<u32>::read(&mut Reader { bytes: &bytes }).is_none());
+    }
+
+    #[test]
+    fn outdated_files_are_rejected() {
+        let dir = cache_dir("outdated_cache_files");
+        let cache = AssetCache::new(&dir);
+        let source = Path::new("assets/BoxTextured.glb");
+        cache.write(source, 7, &load("assets/BoxTextured.glb"));
+
+        assert!(cache.read(source, 7).is_some());
+        assert!(cache.read(source, 8).is_none());
+
+        // Format version, then debug_gltf flag, after the magic bytes
+        let path = cache.file_path(source);
+        let bytes = std::fs::read(&path).unwrap();
+        for offset in [MAGIC.len(), MAGIC.len() + 4] {
+            let mut corrupted = bytes.clone();
+            corrupted[offset] ^= 1;
+            std::fs::write(&path, corrupted).unwrap();
+
+            assert!(cache.read(source, 7).is_none(), "offset {offset}");
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_names_keep_paths_apart() {
+        let cache = AssetCache::new("cache");
+        let slash = cache.file_path(Path::new("models/fox.glb"));
+        let underscore = cache.file_path(Path::new("models_fox.glb"));
+        let gltf = cache.file_path(Path::new("models/fox.gltf"));
+
+        assert_ne!(slash, underscore);
+        assert_ne!(slash, gltf);
+        let name = slash.file_name().unwrap().to_string_lossy();
+        assert!(name.starts_with("models_fox.glb."), "{name}");
+        assert!(name.ends_with(".bin"), "{name}");
+    }
+}
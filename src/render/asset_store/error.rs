@@ -0,0 +1,98 @@
+use std::{fmt, path::PathBuf};
+
+/// Why a model failed to load, naming the glTF element at fault
+#[derive(Debug, Clone)]
+pub enum ModelError {
+    /// File that couldn't be read, the model or one of its external files
+    InvalidPath(PathBuf),
+    /// Not a glTF or GLB file, with the parser message
+    InvalidGltf(String),
+    /// URI of an external file that isn't a relative path
+    InvalidUri(String),
+    InvalidBuffer {
+        buffer: usize,
+    },
+    InvalidImage {
+        image: usize,
+    },
+    InvalidPrimitive {
+        mesh: usize,
+        primitive: usize,
+        accessor: Option<usize>,
+        reason: &'static str,
+    },
+    InvalidAnimation {
+        animation: usize,
+        channel: usize,
+        accessor: Option<usize>,
+        reason: &'static str,
+    },
+    InvalidSkin {
+        skin: usize,
+        accessor: Option<usize>,
+        reason: &'static str,
+    },
+    /// Node breaking the hierarchy, which must be a set of trees
+    InvalidNode {
+        node: usize,
+        reason: &'static str,
+    },
+    /// glTF index or count that doesn't fit in the 32 bits GPU side ones
+    Overflow(&'static str),
+
+    NoScene,
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ModelError::*;
+
+        let accessor = |accessor: &Option<usize>| match accessor {
+            Some(accessor) => format!(" (accessor {accessor})"),
+            None => String::new(),
+        };
+
+        match self {
+            InvalidPath(path) => write!(f, "can't read {}", path.display()),
+            InvalidGltf(message) => write!(f, "invalid glTF: {message}"),
+            InvalidUri(uri) => write!(f, "unsupported URI {uri:?}"),
+            InvalidBuffer { buffer } => write!(f, "buffer {buffer}: missing or too short"),
+            InvalidImage { image } => write!(f, "image {image}: can't be decoded"),
+            InvalidPrimitive {
+                mesh,
+                primitive,
+                accessor: index,
+                reason,
+            } => write!(
+                f,
+                "mesh {mesh} primitive {primitive}{}: {reason}",
+                accessor(index)
+            ),
+            InvalidAnimation {
+                animation,
+                channel,
+                accessor: index,
+                reason,
+            } => write!(
+                f,
+                "animation {animation} channel {channel}{}: {reason}",
+                accessor(index)
+            ),
+            InvalidSkin {
+                skin,
+                accessor: index,
+                reason,
+            } => write!(f, "skin {skin}{}: {reason}", accessor(index)),
+            InvalidNode { node, reason } => write!(f, "node {node}: {reason}"),
+            Overflow(name) => write!(f, "{name} doesn't fit in 32 bits"),
+            NoScene => write!(f, "no scene"),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+/// `value` as a GPU side 32 bits index or count
+pub(super) fn to_u32(value: usize, name: &'static str) -> Result<u32, ModelError> {
+    u32::try_from(value).map_err(|_| ModelError::Overflow(name))
+}
@@ -0,0 +1,16 @@
+/// Custom properties of a glTF element, usually an object of key/values as
+/// exported by Blender, `Null` when the element has none
+pub type Extras = serde_json::Value;
+
+pub(super) fn parse_extras(extras: &gltf::json::Extras) -> Extras {
+    // Raw values are already validated JSON
+    extras
+        .as_ref()
+        .and_then(|raw| serde_json::from_str(raw.get()).ok())
+        .unwrap_or_default()
+}
+
+/// Whether `extras` has `key` set to `value`
+pub(super) fn has_extra(extras: &Extras, key: &str, value: &Extras) -> bool {
+    extras.get(key) == Some(value)
+}
@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use crate::{render::asset_store::ModelError, utils::load_file_buffer};
+
+/// Document with its buffers and decoded images, as [gltf::import] returns
+pub(super) type Import = (
+    gltf::Document,
+    Vec<gltf::buffer::Data>,
+    Vec<gltf::image::Data>,
+);
+
+/// Parses a `.gltf` or `.glb` file, loading the external buffers and images
+/// it references relative to `path` with [load_file_buffer], so that it
+/// works the same on the filesystem and through wasm fetches
+pub(super) async fn import<P: AsRef<Path>>(path: P, bytes: &[u8]) -> Result<Import, ModelError> {
+    use ModelError::*;
+
+    let gltf::Gltf { document, mut blob } =
+        gltf::Gltf::from_slice(bytes).map_err(|error| InvalidGltf(error.to_string()))?;
+    let base = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+
+    let mut buffers = Vec::with_capacity(document.buffers().len());
+    for buffer in document.buffers() {
+        let invalid_buffer = || InvalidBuffer {
+            buffer: buffer.index(),
+        };
+        let data = match buffer.source() {
+            gltf::buffer::Source::Uri(uri) if !is_data_uri(uri) => {
+                let mut data = load_relative_uri(base, uri).await?;
+                // Keep the 4 bytes alignment `gltf` gives to its own buffers
+                while data.len() % 4 != 0 {
+                    data.push(0);
+                }
+                gltf::buffer::Data(data)
+            }
+            source => gltf::buffer::Data::from_source_and_blob(source, None, &mut blob)
+                .map_err(|_| invalid_buffer())?,
+        };
+
+        if data.len() < buffer.length() {
+            return Err(invalid_buffer());
+        }
+        buffers.push(data);
+    }
+
+    let mut images = Vec::with_capacity(document.images().len());
+    for image in document.images() {
+        let invalid_image = || InvalidImage {
+            image: image.index(),
+        };
+        let data = match image.source() {
+            gltf::image::Source::Uri { uri, .. } if !is_data_uri(uri) => {
+                let encoded_image = load_relative_uri(base, uri).await?;
+                decode_image(&encoded_image).ok_or_else(invalid_image)?
+            }
+            // Data URIs don't read anything from the base path, `gltf` only
+            // requires one to be given for them
+            source => gltf::image::Data::from_source(source, Some(base), &buffers)
+                .map_err(|_| invalid_image())?,
+        };
+
+        images.push(data);
+    }
+
+    Ok((document, buffers, images))
+}
+
+fn is_data_uri(uri: &str) -> bool {
+    uri.starts_with("data:")
+}
+
+/// URIs of the buffers and images of `gltf` stored in other files
+pub(super) fn external_uris(gltf: &gltf::Gltf) -> impl Iterator<Item = &str> {
+    let buffer_uris = gltf.buffers().filter_map(|buffer| match buffer.source() {
+        gltf::buffer::Source::Uri(uri) => Some(uri),
+        gltf::buffer::Source::Bin => None,
+    });
+    let image_uris = gltf.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+
+    buffer_uris
+        .chain(image_uris)
+        .filter(|uri| !is_data_uri(uri))
+}
+
+fn relative_uri_path(base: &Path, uri: &str) -> Result<PathBuf, ModelError> {
+    // Only relative references are supported, not other schemes
+    if uri.contains(':') {
+        return Err(ModelError::InvalidUri(uri.to_owned()));
+    }
+
+    let decoded_uri =
+        urlencoding::decode(uri).map_err(|_| ModelError::InvalidUri(uri.to_owned()))?;
+    Ok(base.join(&*decoded_uri))
+}
+
+pub(super) async fn load_relative_uri(base: &Path, uri: &str) -> Result<Vec<u8>, ModelError> {
+    let path = relative_uri_path(base, uri)?;
+
+    #[cfg(feature = "debug_gltf")]
+    log::info!("Loading external file: {:?}", path);
+
+    load_file_buffer(&path)
+        .await
+        .map_err(|_| ModelError::InvalidPath(path))
+}
+
+/// Files holding the external buffers and images of the model file at
+/// `path`, none when it can't be read
+#[cfg(not(target_arch = "wasm32"))]
+pub fn external_files<P: AsRef<Path>>(path: P) -> Vec<PathBuf> {
+    let gltf = (std::fs::read(&path).ok()).and_then(|bytes| gltf::Gltf::from_slice(&bytes).ok());
+    let Some(gltf) = gltf else {
+        return Vec::new();
+    };
+    let base = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+
+    external_uris(&gltf)
+        .filter_map(|uri| relative_uri_path(base, uri).ok())
+        .collect()
+}
+
+fn decode_image(encoded_image: &[u8]) -> Option<gltf::image::Data> {
+    use gltf::image::Format;
+    use image::DynamicImage;
+
+    let image = image::load_from_memory(encoded_image).ok()?;
+
+    let format = match image {
+        DynamicImage::ImageLuma8(_) => Format::R8,
+        DynamicImage::ImageLumaA8(_) => Format::R8G8,
+        DynamicImage::ImageRgb8(_) => Format::R8G8B8,
+        DynamicImage::ImageRgba8(_) => Format::R8G8B8A8,
+        DynamicImage::ImageLuma16(_) => Format::R16,
+        DynamicImage::ImageLumaA16(_) => Format::R16G16,
+        DynamicImage::ImageRgb16(_) => Format::R16G16B16,
+        DynamicImage::ImageRgba16(_) => Format::R16G16B16A16,
+        DynamicImage::ImageRgb32F(_) => Format::R32G32B32FLOAT,
+        DynamicImage::ImageRgba32F(_) => Format::R32G32B32A32FLOAT,
+        _ => return None,
+    };
+
+    Some(gltf::image::Data {
+        format,
+        width: image.width(),
+        height: image.height(),
+        pixels: image.into_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn external_files_are_relative_to_the_model() {
+        let dir = std::env::temp_dir().join(format!("external_files_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model.gltf");
+        let json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "buffers": [
+                { "uri": "model%20data.bin", "byteLength": 4 },
+                { "uri": "data:application/octet-stream;base64,AAAAAA==", "byteLength": 4 }
+            ],
+            "images": [{ "uri": "textures/albedo.png" }, { "uri": "https://example.com/remote.png" }],
+        });
+        std::fs::write(&path, json.to_string()).unwrap();
+
+        let files = external_files(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            files,
+            [dir.join("model data.bin"), dir.join("textures/albedo.png")]
+        );
+        assert!(external_files(dir.join("missing.gltf")).is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use crate::render::asset_store::{AssetCache, ModelData, ModelError};
+
+/// Progress of a model loaded in the background
+#[derive(Debug, Clone)]
+pub enum LoadState {
+    /// Waiting for a worker
+    Queued,
+    /// Being read, parsed and decoded
+    Loading,
+    /// Uploaded and drawn
+    Ready,
+    /// The previous model loaded from the same path, if any, is kept
+    Failed(ModelError),
+}
+
+impl fmt::Display for LoadState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadState::Queued => write!(f, "queued"),
+            LoadState::Loading => write!(f, "loading"),
+            LoadState::Ready => write!(f, "ready"),
+            LoadState::Failed(error) => write!(f, "failed: {error}"),
+        }
+    }
+}
+
+/// Model to load, `generation` telling it apart from the other loads of
+/// the same path
+struct LoadJob {
+    path: PathBuf,
+    generation: u64,
+    cache: Option<AssetCache>,
+}
+
+impl LoadJob {
+    async fn run(self, sender: &mpsc::Sender<LoadMessage>) {
+        let LoadJob {
+            path,
+            generation,
+            cache,
+        } = self;
+
+        let _ = sender.send(LoadMessage::Loading(path.clone(), generation));
+        let result = ModelData::load(&path, cache.as_ref()).await.map(Box::new);
+        let _ = sender.send(LoadMessage::Loaded(path, generation, result));
+    }
+}
+
+enum LoadMessage {
+    Loading(PathBuf, u64),
+    Loaded(PathBuf, u64, Result<Box<ModelData>, ModelError>),
+}
+
+/// State of the last load of a path
+struct PathLoad {
+    path: PathBuf,
+    state: LoadState,
+    /// Number of loads of the path queued so far, messages of the earlier
+    /// ones being stale
+    generation: u64,
+}
+
+/// Reads and parses models off the render loop, natively on a pool of
+/// worker threads and on wasm as tasks of the browser event loop
+pub struct ModelLoader {
+    loads: Vec<PathLoad>,
+    cache: Option<AssetCache>,
+    messages: mpsc::Receiver<LoadMessage>,
+    #[cfg(not(target_arch = "wasm32"))]
+    jobs: mpsc::Sender<LoadJob>,
+    #[cfg(target_arch = "wasm32")]
+    sender: mpsc::Sender<LoadMessage>,
+}
+
+impl Default for ModelLoader {
+    fn default() -> Self {
+        let (sender, messages) = mpsc::channel();
+
+        Self {
+            loads: Vec::new(),
+            cache: None,
+            messages,
+            #[cfg(not(target_arch = "wasm32"))]
+            jobs: spawn_workers(sender),
+            #[cfg(target_arch = "wasm32")]
+            sender,
+        }
+    }
+}
+
+impl ModelLoader {
+    /// Queues the model at `path`, loading it again if it already was. The
+    /// results of the previous loads of `path` still running are dropped
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref().to_path_buf();
+        let load = self.path_load(&path);
+        load.state = LoadState::Queued;
+        load.generation += 1;
+
+        let job = LoadJob {
+            path,
+            generation: load.generation,
+            cache: self.cache.clone(),
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.jobs.send(job).expect("Model loading workers stopped");
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            let sender = self.sender.clone();
+            wasm_bindgen_futures::spawn_local(async move { job.run(&sender).await });
+        }
+    }
+
+    /// Models parsed since the last call, ready to be uploaded
+    pub fn loaded(&mut self) -> Vec<ModelData> {
+        let mut loaded = Vec::new();
+
+        while let Ok(message) = self.messages.try_recv() {
+            match message {
+                LoadMessage::Loading(path, generation) => {
+                    if self.is_current(&path, generation) {
+                        self.path_load(&path).state = LoadState::Loading;
+                    }
+                }
+                LoadMessage::Loaded(path, generation, _) if !self.is_current(&path, generation) => {
+                    #[cfg(feature = "debug_gltf")]
+                    log::info!("Dropping a stale load of {}", path.display());
+                }
+                LoadMessage::Loaded(_, _, Ok(data)) => loaded.push(*data),
+                LoadMessage::Loaded(path, _, Err(error)) => {
+                    log::error!("Failed to load model {}: {}", path.display(), error);
+                    self.path_load(&path).state = LoadState::Failed(error);
+                }
+            }
+        }
+
+        loaded
+    }
+
+    /// On-disk cache of the models queued from now on
+    pub fn set_cache(&mut self, cache: Option<AssetCache>) {
+        self.cache = cache;
+    }
+
+    pub fn set_ready(&mut self, path: &Path) {
+        self.path_load(path).state = LoadState::Ready;
+    }
+
+    pub fn states(&self) -> impl Iterator<Item = (&Path, &LoadState)> {
+        (self.loads.iter()).map(|load| (load.path.as_path(), &load.state))
+    }
+
+    /// Whether `generation` is the last load queued for `path`
+    fn is_current(&self, path: &Path, generation: u64) -> bool {
+        (self.loads.iter()).any(|load| load.path == path && load.generation == generation)
+    }
+
+    fn path_load(&mut self, path: &Path) -> &mut PathLoad {
+        let index = match self.loads.iter().position(|load| load.path == path) {
+            Some(index) => index,
+            None => {
+                self.loads.push(PathLoad {
+                    path: path.to_path_buf(),
+                    state: LoadState::Queued,
+                    generation: 0,
+                });
+                self.loads.len() - 1
+            }
+        };
+
+        &mut self.loads[index]
+    }
+}
+
+/// Workers taking turns on the queued paths, sending back the parsed models
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_workers(sender: mpsc::Sender<LoadMessage>) -> mpsc::Sender<LoadJob> {
+    use std::sync::{Arc, Mutex};
+
+    let (jobs, queue) = mpsc::channel::<LoadJob>();
+    let queue = Arc::new(Mutex::new(queue));
+
+    let worker_count = std::thread::available_parallelism().map_or(1, |count| count.get());
+    for i in 0..worker_count.min(4) {
+        let queue = queue.clone();
+        let sender = sender.clone();
+
+        std::thread::Builder::new()
+            .name(format!("model loader #{i}"))
+            .spawn(move || loop {
+                // Stops once the loader is dropped
+                let Ok(job) = queue.lock().expect("Poisoned model queue").recv() else {
+                    return;
+                };
+
+                pollster::block_on(job.run(&sender));
+            })
+            .expect("Could not spawn a model loading worker");
+    }
+
+    jobs
+}
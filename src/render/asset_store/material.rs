@@ -2,6 +2,14 @@ use gltf::material::NormalTexture;
 use gltf::material::OcclusionTexture;
 use gltf::texture::Info;
 
+use crate::render::{
+    asset_store::{
+        cache::{Cached, Reader},
+        extras::{parse_extras, Extras},
+    },
+    texture::ColorSpace,
+};
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum AlphaMode {
@@ -31,6 +39,37 @@ impl From<gltf::material::AlphaMode> for AlphaMode {
 pub struct TextureInfo {
     pub texture_index: usize,
     pub tex_index: u32,
+    /// glTF sampler of the texture, the default sampler is used when unset
+    pub sampler_index: Option<usize>,
+    /// `KHR_texture_transform` applied to the UVs before sampling
+    pub transform: glam::Mat3,
+}
+
+/// Uniform read by the shader when sampling a material texture
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TextureUniform {
+    /// Columns of the UV transform, padded to 16 bytes like a WGSL `mat3x3`
+    transform: [[f32; 4]; 3],
+    /// UV set to sample with, `TEXCOORD_<tex_coord>`
+    tex_coord: u32,
+    _padding: [u32; 3],
+}
+
+impl From<&TextureInfo> for TextureUniform {
+    fn from(texture_info: &TextureInfo) -> Self {
+        let transform = texture_info.transform;
+
+        Self {
+            transform: [
+                transform.x_axis.extend(0.0).into(),
+                transform.y_axis.extend(0.0).into(),
+                transform.z_axis.extend(0.0).into(),
+            ],
+            tex_coord: texture_info.tex_index,
+            _padding: [0; 3],
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -42,7 +81,6 @@ pub struct MetallicRoughness {
 
 #[derive(Clone, Debug)]
 pub struct Material {
-    #[cfg(feature = "debug_gltf")]
     pub name: Option<String>,
     pub color: [f32; 4],
     pub emissive: [f32; 3],
@@ -55,6 +93,24 @@ pub struct Material {
     pub alpha_mode: AlphaMode,
     pub alpha_cutoff: f32,
     pub double_sided: bool,
+    pub extras: Extras,
+}
+
+impl Material {
+    /// Every texture of the material, with the color space its slot reads
+    pub fn textures(&self) -> impl Iterator<Item = (TextureInfo, ColorSpace)> {
+        use ColorSpace::{Linear, Srgb};
+
+        [
+            (self.color_texture, Srgb),
+            (self.emissive_texture, Srgb),
+            (self.normals_texture, Linear),
+            (self.occlusion_texture, Linear),
+            (self.metallic_roughness.metallic_roughness_texture, Linear),
+        ]
+        .into_iter()
+        .filter_map(|(texture, color_space)| Some((texture?, color_space)))
+    }
 }
 
 impl<'a> From<gltf::Material<'a>> for Material {
@@ -85,7 +141,6 @@ impl<'a> From<gltf::Material<'a>> for Material {
         let double_sided = material.double_sided();
 
         Material {
-            #[cfg(feature = "debug_gltf")]
             name: material.name().map(ToOwned::to_owned),
             color,
             emissive,
@@ -98,21 +153,46 @@ impl<'a> From<gltf::Material<'a>> for Material {
             alpha_mode,
             alpha_cutoff,
             double_sided,
+            extras: parse_extras(material.extras()),
         }
     }
 }
 
 fn get_texture(texture_info: Option<Info>) -> Option<TextureInfo> {
-    texture_info.map(|tex_info| TextureInfo {
-        texture_index: tex_info.texture().source().index(),
-        tex_index: tex_info.tex_coord(),
+    texture_info.map(|tex_info| {
+        let texture_transform = tex_info.texture_transform();
+        let transform = texture_transform
+            .as_ref()
+            .map_or(glam::Mat3::IDENTITY, |transform| {
+                // translation * rotation * scale, the rotation turns the UVs
+                // counter-clockwise with V pointing down
+                glam::Mat3::from_scale_angle_translation(
+                    transform.scale().into(),
+                    -transform.rotation(),
+                    transform.offset().into(),
+                )
+            });
+        let tex_index = texture_transform
+            .and_then(|transform| transform.tex_coord())
+            .unwrap_or_else(|| tex_info.tex_coord());
+
+        TextureInfo {
+            texture_index: tex_info.texture().source().index(),
+            tex_index,
+            sampler_index: tex_info.texture().sampler().index(),
+            transform,
+        }
     })
 }
 
+// `gltf` doesn't parse `KHR_texture_transform` on normal and occlusion
+// textures, they are left untransformed
 fn get_normals_texture(texture_info: Option<NormalTexture>) -> Option<TextureInfo> {
     texture_info.map(|tex_info| TextureInfo {
         texture_index: tex_info.texture().source().index(),
         tex_index: tex_info.tex_coord(),
+        sampler_index: tex_info.texture().sampler().index(),
+        transform: glam::Mat3::IDENTITY,
     })
 }
 
@@ -124,7 +204,86 @@ fn get_occlusion(texture_info: Option<OcclusionTexture>) -> (f32, Option<Texture
     let texture = texture_info.map(|tex_info| TextureInfo {
         texture_index: tex_info.texture().source().index(),
         tex_index: tex_info.tex_coord(),
+        sampler_index: tex_info.texture().sampler().index(),
+        transform: glam::Mat3::IDENTITY,
     });
 
     (strength, texture)
 }
+
+impl Cached for AlphaMode {
+    fn write(&self, writer: &mut Vec<u8>) {
+        (*self as u8).write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        match u8::read(reader)? {
+            0 => Some(AlphaMode::Opaque),
+            1 => Some(AlphaMode::Mask),
+            2 => Some(AlphaMode::Blend),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for TextureInfo {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.texture_index.write(writer);
+        self.tex_index.write(writer);
+        self.sampler_index.write(writer);
+        self.transform.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            texture_index: Cached::read(reader)?,
+            tex_index: Cached::read(reader)?,
+            sampler_index: Cached::read(reader)?,
+            transform: Cached::read(reader)?,
+        })
+    }
+}
+
+impl Cached for Material {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.name.write(writer);
+        self.color.write(writer);
+        self.emissive.write(writer);
+        self.occlusion.write(writer);
+        self.color_texture.write(writer);
+        self.emissive_texture.write(writer);
+        self.normals_texture.write(writer);
+        self.occlusion_texture.write(writer);
+        self.metallic_roughness.metallic.write(writer);
+        self.metallic_roughness.roughness.write(writer);
+        self.metallic_roughness
+            .metallic_roughness_texture
+            .write(writer);
+        self.alpha_mode.write(writer);
+        self.alpha_cutoff.write(writer);
+        self.double_sided.write(writer);
+        self.extras.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            name: Cached::read(reader)?,
+            color: Cached::read(reader)?,
+            emissive: Cached::read(reader)?,
+            occlusion: Cached::read(reader)?,
+            color_texture: Cached::read(reader)?,
+            emissive_texture: Cached::read(reader)?,
+            normals_texture: Cached::read(reader)?,
+            occlusion_texture: Cached::read(reader)?,
+            metallic_roughness: MetallicRoughness {
+                metallic: Cached::read(reader)?,
+                roughness: Cached::read(reader)?,
+                metallic_roughness_texture: Cached::read(reader)?,
+            },
+            alpha_mode: Cached::read(reader)?,
+            alpha_cutoff: Cached::read(reader)?,
+            double_sided: Cached::read(reader)?,
+            extras: Cached::read(reader)?,
+        })
+    }
+}
@@ -2,53 +2,19 @@
 use crate::render::asset_store::utils::indent;
 use crate::render::{
     asset_store::{
-        animation::Channel, material::Material, mesh_tangent::generate_tangents, MeshIndex,
-        NodeIndex, NodeLayout,
+        cache::{Cached, Reader},
+        error::{to_u32, ModelError},
+        material::Material,
+        mesh_tangent::generate_tangents,
+        skin::Skin,
+        MeshIndex, NodeIndex, NodeLayout,
     },
     shaders::kind::ShaderKinds,
 };
 
+use gltf::Semantic;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-pub struct Aabb {
-    min: glam::Vec3,
-    max: glam::Vec3,
-}
-
-impl Aabb {
-    pub const ZERO: Self = Self {
-        min: glam::Vec3::ZERO,
-        max: glam::Vec3::ZERO,
-    };
-
-    pub fn unions(aabbs: &[Self]) -> Self {
-        if aabbs.is_empty() {
-            return Self::ZERO;
-        }
-
-        let mut min = glam::Vec3::splat(f32::MAX);
-        let mut max = glam::Vec3::splat(f32::MIN);
-
-        for aabb in aabbs {
-            min = min.min(aabb.min);
-            max = max.max(aabb.max);
-        }
-
-        Self { min, max }
-    }
-
-    pub fn center(&self) -> glam::Vec3 {
-        (self.min + self.max) / 2.0
-    }
-
-    pub fn union(&self, other: &Self) -> Self {
-        Self {
-            min: self.min.min(other.min),
-            max: self.max.max(other.max),
-        }
-    }
-}
-
 #[repr(align(16), C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct PrimitiveVertex {
@@ -108,6 +74,41 @@ impl PrimitiveVertex {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceData {
+    pub transform: glam::Mat4,
+    /// Offset in the model joint matrices, [InstanceData::NO_JOINTS] if the
+    /// instance is not skinned
+    pub joint_offset: u32,
+    _padding: [u32; 3],
+}
+
+impl InstanceData {
+    pub const NO_JOINTS: u32 = u32::MAX;
+
+    pub fn new(transform: glam::Mat4, joint_offset: u32) -> Self {
+        Self {
+            transform,
+            joint_offset,
+            _padding: [0; 3],
+        }
+    }
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+            11 => Float32x4, 12 => Float32x4, 13 => Float32x4, 14 => Float32x4,
+            15 => Uint32
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
 impl PrimitiveVertex {
     pub const DEFAULT_NORMAL: [f32; 3] = [1f32; 3];
     pub const DEFAULT_TEX: [f32; 2] = [0f32; 2];
@@ -117,86 +118,141 @@ impl PrimitiveVertex {
     pub const DEFAULT_COLOR: [f32; 4] = [1f32; 4];
 }
 
+/// Per vertex displacements of a morph target, missing attributes are not morphed
+#[derive(Clone)]
+pub struct MorphTarget {
+    pub positions: Option<Vec<glam::Vec3>>,
+    pub normals: Option<Vec<glam::Vec3>>,
+    pub tangents: Option<Vec<glam::Vec3>>,
+}
+
+impl MorphTarget {
+    /// Adds the weighted displacements of `targets` to `vertices`
+    pub fn apply(targets: &[Self], weights: &[f32], vertices: &mut [PrimitiveVertex]) {
+        for (target, weight) in targets.iter().zip(weights) {
+            if *weight == 0f32 {
+                continue;
+            }
+
+            if let Some(positions) = &target.positions {
+                for (vertex, delta) in vertices.iter_mut().zip(positions) {
+                    vertex.position += *weight * *delta;
+                }
+            }
+            if let Some(normals) = &target.normals {
+                for (vertex, delta) in vertices.iter_mut().zip(normals) {
+                    vertex.normal += *weight * *delta;
+                }
+            }
+            if let Some(tangents) = &target.tangents {
+                for (vertex, delta) in vertices.iter_mut().zip(tangents) {
+                    vertex.tangent += (*weight * *delta).extend(0f32);
+                }
+            }
+        }
+
+        if targets.iter().any(|target| target.normals.is_some()) {
+            for vertex in vertices.iter_mut() {
+                vertex.normal = vertex.normal.normalize_or_zero();
+            }
+        }
+    }
+}
+
+impl Cached for MorphTarget {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.positions.write(writer);
+        self.normals.write(writer);
+        self.tangents.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            positions: Cached::read(reader)?,
+            normals: Cached::read(reader)?,
+            tangents: Cached::read(reader)?,
+        })
+    }
+}
+
 pub struct Primitive {
     pub index: usize,
     pub vertices: Vec<PrimitiveVertex>,
     pub indices: Option<Vec<u32>>,
+    pub topology: wgpu::PrimitiveTopology,
     pub material: Material,
-    pub aabb: Aabb,
-    pub instance_transforms: Vec<glam::Mat4>,
-    pub instance_count: u32,
-    pub instance_animations: Vec<Vec<Channel>>,
-
-    #[cfg(feature = "debug_gltf")]
+    pub instances: Vec<InstanceData>,
     pub instance_node_indices: Vec<NodeIndex>,
+    pub morph_targets: Vec<MorphTarget>,
 }
 
 pub struct Mesh {
-    #[cfg(feature = "debug_gltf")]
     pub name: Option<String>,
     pub primitives: Vec<Primitive>,
-    pub aabb: Aabb,
 }
 
-static mut PRIMITIVE_COUNT: AtomicUsize = AtomicUsize::new(0);
+static PRIMITIVE_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 impl Mesh {
-    fn new(primitives: Vec<Primitive>, aabb: Aabb, _name: Option<String>) -> Self {
-        Self {
-            #[cfg(feature = "debug_gltf")]
-            name: _name,
-            primitives,
-            aabb,
-        }
+    fn new(primitives: Vec<Primitive>, name: Option<String>) -> Self {
+        Self { name, primitives }
     }
 
     pub fn parse(
         node_layout: &NodeLayout,
+        skins: &[Skin],
         mesh: &gltf::Mesh,
         buffers: &[gltf::buffer::Data],
-    ) -> Self {
+    ) -> Result<Self, ModelError> {
         #[cfg(feature = "debug_gltf")]
         log::info!("{}Mesh#{}: {:?}", indent(), mesh.index(), mesh.name());
 
         let mut primitives: Vec<Primitive> = Vec::new();
-        let mut global_aabb = Aabb::ZERO;
 
         for primitive in mesh.primitives() {
-            let index = unsafe { PRIMITIVE_COUNT.fetch_add(1, Ordering::Relaxed) };
+            let index = PRIMITIVE_COUNT.fetch_add(1, Ordering::Relaxed);
             let material: Material = primitive.material().into();
 
-            let mesh_index = u32::try_from(mesh.index()).expect("Mesh index overflow");
-            let mesh_index = MeshIndex(mesh_index);
-            let mesh_nodes = node_layout.mesh_nodes.get(&mesh_index).unwrap();
+            let error = |accessor: Option<gltf::Accessor>, reason| ModelError::InvalidPrimitive {
+                mesh: mesh.index(),
+                primitive: primitive.index(),
+                accessor: accessor.map(|accessor| accessor.index()),
+                reason,
+            };
 
-            let instance_animations = mesh_nodes
-                .iter()
-                // TODO: Try to remove the clone
-                .map(|node_index| node_layout.get_node_animations(*node_index).clone())
-                .collect::<Vec<_>>();
+            let mesh_index = MeshIndex(to_u32(mesh.index(), "mesh index")?);
+            // Meshes can be left unused by every node
+            let mesh_nodes = node_layout
+                .mesh_nodes
+                .get(&mesh_index)
+                .map_or(&[][..], Vec::as_slice);
 
-            let instance_transforms = mesh_nodes
+            let instances = mesh_nodes
                 .iter()
-                .map(|node_index| node_layout.get_node_transform(*node_index))
+                .map(|node_index| {
+                    let index = usize::try_from(node_index.0).expect("Node index overflow");
+                    let skin = node_layout.nodes[index].skin.map(|skin| {
+                        let skin_index = usize::try_from(skin.0).expect("Skin index overflow");
+                        &skins[skin_index]
+                    });
+
+                    // Skinned vertices are already placed by their joints
+                    match skin {
+                        Some(skin) => InstanceData::new(glam::Mat4::IDENTITY, skin.joint_offset),
+                        None => InstanceData::new(
+                            node_layout.get_node_transform(*node_index),
+                            InstanceData::NO_JOINTS,
+                        ),
+                    }
+                })
                 .collect::<Vec<_>>();
-            let instance_count =
-                u32::try_from(instance_transforms.len()).expect("Instance count overflow");
 
             let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
-            let aabb = {
-                let bounds = primitive.bounding_box();
-                let min = bounds.min.into();
-                let max = bounds.max.into();
-
-                Aabb { min, max }
-            };
-
-            global_aabb = global_aabb.union(&aabb);
-
             let mut shader_kinds = ShaderKinds::NONE;
 
-            let positions = read_positions(&reader);
+            let positions = read_positions(&reader)
+                .ok_or_else(|| error(primitive.get(&Semantic::Positions), "missing POSITION"))?;
             let positions_len = positions.len();
 
             let normals = read_normals(&reader);
@@ -207,6 +263,43 @@ impl Mesh {
             let joints = read_joints(&reader);
             let colors = read_colors(&reader);
 
+            #[rustfmt::skip]
+            let attribute_lens = [
+                (Semantic::Normals, normals.as_ref().map(Vec::len)),
+                (Semantic::Tangents, tangents.as_ref().map(Vec::len)),
+                (Semantic::TexCoords(0), tex_coords_0.as_ref().map(Vec::len)),
+                (Semantic::TexCoords(1), tex_coords_1.as_ref().map(Vec::len)),
+                (Semantic::Weights(0), weights.as_ref().map(Vec::len)),
+                (Semantic::Joints(0), joints.as_ref().map(Vec::len)),
+                (Semantic::Colors(0), colors.as_ref().map(Vec::len)),
+            ];
+            for (semantic, len) in attribute_lens {
+                if len.is_some_and(|len| len != positions_len) {
+                    let reason = "attribute count differs from POSITION";
+                    return Err(error(primitive.get(&semantic), reason));
+                }
+            }
+
+            // Joints index the skin of each node using the mesh, reading past
+            // its joint count would pick the matrices of another skin
+            let max_joint = joints.iter().flatten().flatten().max();
+            let node_skins = (mesh_nodes.iter()).filter_map(|node_index| {
+                let index = usize::try_from(node_index.0).expect("Node index overflow");
+                let skin = node_layout.nodes[index].skin?;
+                Some(usize::try_from(skin.0).expect("Skin index overflow"))
+            });
+            for skin in node_skins {
+                if max_joint.is_some_and(|&joint| joint >= skins[skin].joint_count()) {
+                    return Err(ModelError::InvalidSkin {
+                        skin,
+                        accessor: primitive
+                            .get(&Semantic::Joints(0))
+                            .map(|accessor| accessor.index()),
+                        reason: "JOINTS_0 index out of range",
+                    });
+                }
+            }
+
             let has_normals = (normals.is_some(), ShaderKinds::NORMAL);
             let has_tangents = (tangents.is_some(), ShaderKinds::TANGENT);
             let has_tex_coords_0 = (tex_coords_0.is_some(), ShaderKinds::TEX_COORD_0);
@@ -260,8 +353,18 @@ impl Mesh {
                 ));
             }
 
+            let morph_targets = read_morph_targets(&reader);
+
             let indices = read_indices(&reader);
-            if !positions.is_empty()
+            if let Some(indices) = &indices {
+                if indices.iter().any(|&index| index as usize >= positions_len) {
+                    return Err(error(primitive.indices(), "vertex index out of range"));
+                }
+            }
+            let (topology, indices) = read_topology(primitive.mode(), indices, positions_len)?;
+            // mikktspace only works on triangle lists
+            if topology == wgpu::PrimitiveTopology::TriangleList
+                && !positions.is_empty()
                 && shader_kinds.is_normal()
                 && shader_kinds.is_tex_coord()
                 && !shader_kinds.is_tangent()
@@ -276,24 +379,18 @@ impl Mesh {
                 index,
                 vertices,
                 indices,
+                topology,
                 material,
-                aabb,
-                instance_transforms,
-                instance_count,
-                instance_animations,
-
-                #[cfg(feature = "debug_gltf")]
-                instance_node_indices: mesh_nodes.clone(),
+                instances,
+                instance_node_indices: mesh_nodes.to_vec(),
+                morph_targets,
             };
             primitives.push(primitive);
         }
 
-        #[cfg(feature = "debug_gltf")]
         let name = mesh.name().map(|s| s.to_string());
-        #[cfg(not(feature = "debug_gltf"))]
-        let name = None;
 
-        Mesh::new(primitives, global_aabb, name)
+        Ok(Mesh::new(primitives, name))
     }
 }
 
@@ -307,14 +404,56 @@ where
         .map(|indices| indices.into_u32().collect::<Vec<_>>())
 }
 
-fn read_positions<'a, 's, F>(reader: &gltf::mesh::Reader<'a, 's, F>) -> Vec<[f32; 3]>
+/// wgpu topology drawing `mode`, with the indices to draw it. Line loops and
+/// triangle fans have no wgpu equivalent, they are indexed as line strips and
+/// triangle lists instead
+fn read_topology(
+    mode: gltf::mesh::Mode,
+    indices: Option<Vec<u32>>,
+    vertex_count: usize,
+) -> Result<(wgpu::PrimitiveTopology, Option<Vec<u32>>), ModelError> {
+    use gltf::mesh::Mode;
+    use wgpu::PrimitiveTopology;
+
+    let sequential_indices = |indices: Option<Vec<u32>>| match indices {
+        Some(indices) => Ok(indices),
+        None => Ok((0..to_u32(vertex_count, "vertex count")?).collect()),
+    };
+
+    let topology = match mode {
+        Mode::Points => (PrimitiveTopology::PointList, indices),
+        Mode::Lines => (PrimitiveTopology::LineList, indices),
+        Mode::LineStrip => (PrimitiveTopology::LineStrip, indices),
+        Mode::Triangles => (PrimitiveTopology::TriangleList, indices),
+        Mode::TriangleStrip => (PrimitiveTopology::TriangleStrip, indices),
+        Mode::LineLoop => {
+            let mut indices = sequential_indices(indices)?;
+            if let Some(&first) = indices.first() {
+                indices.push(first);
+            }
+            (PrimitiveTopology::LineStrip, Some(indices))
+        }
+        Mode::TriangleFan => {
+            let indices = sequential_indices(indices)?;
+            let triangles = match indices.split_first() {
+                Some((&center, rest)) => rest
+                    .windows(2)
+                    .flat_map(|edge| [center, edge[0], edge[1]])
+                    .collect(),
+                None => Vec::new(),
+            };
+            (PrimitiveTopology::TriangleList, Some(triangles))
+        }
+    };
+
+    Ok(topology)
+}
+
+fn read_positions<'a, 's, F>(reader: &gltf::mesh::Reader<'a, 's, F>) -> Option<Vec<[f32; 3]>>
 where
     F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
 {
-    reader
-        .read_positions()
-        .expect("Position primitives should be present")
-        .collect()
+    reader.read_positions().map(|positions| positions.collect())
 }
 
 fn read_normals<'a, 's, F>(reader: &gltf::mesh::Reader<'a, 's, F>) -> Option<Vec<[f32; 3]>>
@@ -372,3 +511,60 @@ where
         .read_colors(0)
         .map(|colors| colors.into_rgba_f32().collect())
 }
+
+fn read_morph_targets<'a, 's, F>(reader: &gltf::mesh::Reader<'a, 's, F>) -> Vec<MorphTarget>
+where
+    F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+{
+    reader
+        .read_morph_targets()
+        .map(|(positions, normals, tangents)| MorphTarget {
+            positions: positions.map(|positions| positions.map(glam::Vec3::from).collect()),
+            normals: normals.map(|normals| normals.map(glam::Vec3::from).collect()),
+            tangents: tangents.map(|tangents| tangents.map(glam::Vec3::from).collect()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::asset_store::{ModelData, ModelError};
+
+    /// Triangle skinned by a node with `joint_count` joints, its vertices
+    /// using the joints `joints`
+    fn skinned_triangle(joint_count: usize, joints: [u8; 4]) -> Result<ModelData, ModelError> {
+        let positions = [[0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let mut bytes = bytemuck::cast_slice::<_, u8>(&positions).to_vec();
+        bytes.extend_from_slice(&[joints; 3].concat());
+
+        let json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": bytes.len() }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 12 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0] },
+                { "bufferView": 1, "componentType": 5121, "count": 3, "type": "VEC4" }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "JOINTS_0": 1 } }] }],
+            "skins": [{ "joints": (1..=joint_count).collect::<Vec<_>>() }],
+            "nodes": [{ "mesh": 0, "skin": 0 }, {}, {}, {}],
+            "scenes": [{ "nodes": [0, 1, 2, 3] }],
+        });
+        let gltf = gltf::Gltf::from_slice(json.to_string().as_bytes()).expect("Invalid test glTF");
+        let buffers = vec![gltf::buffer::Data(bytes)];
+
+        ModelData::from_import("skinned.gltf", (gltf.document, buffers, Vec::new()))
+    }
+
+    #[test]
+    fn joints_must_belong_to_the_skin() {
+        assert!(skinned_triangle(3, [0, 1, 2, 0]).is_ok());
+        assert!(matches!(
+            skinned_triangle(2, [0, 1, 2, 0]),
+            Err(ModelError::InvalidSkin { skin: 0, .. })
+        ));
+    }
+}
@@ -1,30 +1,58 @@
-use crate::utils::Instant;
-use std::path::Path;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use wgpu::util::DeviceExt;
 
 use crate::{
     render::asset_store::{
-        animation::Channel,
-        material::Material,
-        mesh::{Aabb, Mesh},
+        animation::Animation,
+        material::{AlphaMode, Material, TextureUniform},
+        mesh::{Mesh, MorphTarget},
         node_layout::NodeLayout,
+        pose::Pose,
+        sampler::{SamplerKey, Samplers},
+        skin::Skin,
+        textures::{TextureImages, Textures},
     },
-    render::Texture,
+    render::{camera::Projection, texture::ColorSpace},
     utils::load_file_buffer,
 };
 
 mod animation;
+mod animation_player;
+mod cache;
+mod error;
+mod extras;
+mod import;
+mod loader;
 mod material;
 mod mesh;
 mod mesh_tangent;
+mod node;
 mod node_layout;
+mod pose;
+mod sampler;
+mod skin;
+mod textures;
 mod utils;
+mod validation;
 mod world;
 
-pub use material::TextureInfo;
-pub use mesh::PrimitiveVertex;
+pub use animation_player::{AnimationPlayer, LayerBlend, PlaybackMode, PlaybackState};
+pub use cache::AssetCache;
+pub use error::ModelError;
+pub use extras::Extras;
+#[cfg(not(target_arch = "wasm32"))]
+pub use import::external_files;
+pub use loader::LoadState;
+pub use mesh::{InstanceData, PrimitiveVertex};
+pub use node::Node;
 pub use node_layout::{MeshIndex, NodeIndex};
+pub use skin::joint_matrices_bind_group_layout;
+pub use validation::{validate, Diagnostic, Severity};
 pub use world::AssetRegistry;
 
 #[derive(Debug, Clone)]
@@ -46,8 +74,9 @@ pub struct ModelMetadata {
 #[cfg(feature = "debug_gltf")]
 impl ModelMetadata {
     pub fn new<P: AsRef<Path>>(path: P, gltf: &gltf::Document) -> Self {
-        let name = path.as_ref().file_name().unwrap().to_str().unwrap();
-        let path = path.as_ref().to_str().unwrap();
+        let name = path.as_ref().file_name().unwrap_or_default();
+        let name = name.to_string_lossy();
+        let path = path.as_ref().to_string_lossy();
 
         let scene_count = gltf.scenes().len();
         let mesh_count = gltf.meshes().len();
@@ -60,8 +89,8 @@ impl ModelMetadata {
         // let light_count = gltf.lights().len();
 
         Self {
-            name: String::from(name),
-            path: String::from(path),
+            name: name.into_owned(),
+            path: path.into_owned(),
             scene_count,
             mesh_count,
             texture_count,
@@ -75,240 +104,671 @@ impl ModelMetadata {
     }
 }
 
-pub struct Model {
-    index: usize,
+/// Buffers, textures and animations of a model file, uploaded once and
+/// shared by every [Model] placed from it
+pub struct ModelAsset {
+    /// File the asset was loaded from, to reload it
+    path: PathBuf,
 
     #[cfg(feature = "debug_gltf")]
     metadata: ModelMetadata,
     packed_primitives: PackedPrimitives,
-    textures: Vec<Texture>,
+    /// Bind group of the color texture of each primitive, if it has one
+    color_textures: Vec<Option<wgpu::BindGroup>>,
+
+    node_layout: NodeLayout,
+    default_scene: usize,
+    cameras: Vec<Projection>,
+    animations: Vec<Animation>,
+    skins: Vec<Skin>,
+    joint_count: u32,
+}
+
+impl ModelAsset {
+    fn is_transparent(&self) -> bool {
+        (self.packed_primitives.per_primitives.iter())
+            .any(|primitive| primitive.material.alpha_mode != AlphaMode::Opaque)
+    }
+}
+
+/// Model placed in the world, with its own scene and animation state on top
+/// of a shared [ModelAsset]
+pub struct Model {
+    index: usize,
+    asset: Rc<ModelAsset>,
+    /// Placement of the model, parent of the scene root nodes
+    transform: glam::Mat4,
+
+    active_scene: usize,
+    /// Nodes reachable from the active scene, the only ones instantiated
+    scene_nodes: HashSet<NodeIndex>,
+    /// Set when the instances need to be written again, even in the same
+    /// pose
+    pose_outdated: bool,
+    /// Last computed pose, with the world transforms of every node in it
+    pose: Pose,
+    global_transforms: Vec<glam::Mat4>,
+    animation_player: AnimationPlayer,
+    joint_matrices: Vec<glam::Mat4>,
+    joint_matrices_buffer: wgpu::Buffer,
+    joint_matrices_bind_group: wgpu::BindGroup,
+
+    cached_instances: Vec<Option<PrimitiveInstances>>,
+}
+
+/// Instances of a primitive in the last computed pose of a [Model], whose
+/// buffers are written again while the active scene keeps their count
+struct PrimitiveInstances {
+    transforms_buffer: wgpu::Buffer,
+    count: u32,
+    /// Morphed copy of the primitive vertices for each instance, with the
+    /// vertex count of a copy
+    morphed_vertices: Option<(wgpu::Buffer, u32)>,
+}
+
+impl PrimitiveInstances {
+    /// Buffers for `count` instances of `primitive`, written by each update
+    fn new(device: &wgpu::Device, primitive: &PerPrimitive, count: u32) -> Self {
+        let buffer = |label, size: usize| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (size * count as usize) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+
+        let transforms_buffer = buffer(
+            "Instance Transform Buffer",
+            std::mem::size_of::<InstanceData>(),
+        );
+        let morphed_vertices = (!primitive.morph_targets.is_empty()).then(|| {
+            let vertex_count = primitive.staging_vertex.len();
+            let vertex_buffer = buffer(
+                "Morphed Vertex Buffer",
+                vertex_count * std::mem::size_of::<PrimitiveVertex>(),
+            );
+            let vertex_count = u32::try_from(vertex_count).expect("Not a valid vertex count");
+
+            (vertex_buffer, vertex_count)
+        });
 
-    cached_model_render: Vec<Option<ModelRender>>,
+        Self {
+            transforms_buffer,
+            count,
+            morphed_vertices,
+        }
+    }
 }
 
-pub struct ModelRender {
+pub struct ModelRender<'a> {
     #[cfg(feature = "debug_gltf")]
-    pub metadata: ModelMetadata,
+    pub metadata: &'a ModelMetadata,
 
-    pub instance_transforms_buffer: wgpu::Buffer,
+    pub instance_transforms_buffer: &'a wgpu::Buffer,
     pub instance_count: u32,
 
-    pub vertex_buffer: wgpu::Buffer,
-    pub index_buffer: Option<wgpu::Buffer>,
+    pub vertices: wgpu::BufferSlice<'a>,
+    pub indices: Option<wgpu::BufferSlice<'a>>,
+    pub topology: wgpu::PrimitiveTopology,
 
-    pub color_texture: Option<wgpu::BindGroup>,
+    pub color_texture: Option<&'a wgpu::BindGroup>,
 
     pub vertex_count: u32,
+    /// Set when each instance has its own morphed copy of the vertices,
+    /// instance `i` vertices then start at `i * morphed_vertex_count`
+    pub morphed_vertex_count: Option<u32>,
 }
 
 impl Model {
-    fn update_index(&mut self, device: &wgpu::Device, index: usize, start_time: &Instant) {
-        let mesh = &self.packed_primitives.per_primitives[index];
+    /// Places a model of `asset` at `transform`, in its default scene and
+    /// rest pose
+    pub fn new(asset: Rc<ModelAsset>, transform: glam::Mat4, device: &wgpu::Device) -> Self {
+        let active_scene = asset.default_scene;
+        let scene_nodes = asset.node_layout.scene_nodes(active_scene);
+        let pose = asset.node_layout.rest_pose();
+        let global_transforms = (asset.node_layout.global_transforms(&pose.transforms))
+            .into_iter()
+            .map(|global_transform| transform * global_transform)
+            .collect();
+
+        // Storage buffers can't be empty, keep at least one matrix around
+        let joint_matrices = vec![glam::Mat4::IDENTITY; asset.joint_count.max(1) as usize];
+        let joint_matrices_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Joint Matrices Buffer"),
+            contents: bytemuck::cast_slice(&joint_matrices),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let joint_matrices_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &joint_matrices_bind_group_layout(device),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: joint_matrices_buffer.as_entire_binding(),
+            }],
+            label: Some("Joint Matrices Bind Group"),
+        });
 
-        // TODO: remove clone
-        let mut mesh_instance_transforms = mesh.instance_transforms.clone();
+        let animation_player = AnimationPlayer::new(asset.animations.len());
 
-        if let Some(_cached) = &self.cached_model_render[index] {
-            let mut cached = true;
+        Model {
+            index: MODEL_INDEX.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            asset,
+            transform,
 
-            let elapsed_time = start_time.elapsed().as_micros() as f32 / 1e6;
+            active_scene,
+            scene_nodes,
+            pose_outdated: true,
+            pose,
+            global_transforms,
+            animation_player,
+            joint_matrices,
+            joint_matrices_buffer,
+            joint_matrices_bind_group,
 
-            for (index, primitive_animations) in mesh.instance_animations.iter().enumerate() {
-                if primitive_animations.is_empty() {
-                    continue;
-                }
+            cached_instances: Vec::new(),
+        }
+    }
 
-                cached = false;
-
-                let mut node_transform = mesh_instance_transforms[index];
-
-                use animation::PropertyValue;
-                for animation_channel in primitive_animations {
-                    let interpolation = animation_channel.interpolate(elapsed_time);
-
-                    node_transform = match interpolation {
-                        PropertyValue::Rotation(rotation) => {
-                            node_transform * glam::Mat4::from_quat(rotation)
-                        }
-                        PropertyValue::Scale(scale) => {
-                            node_transform * glam::Mat4::from_scale(scale)
-                        }
-                        PropertyValue::Translation(translation) => {
-                            node_transform * glam::Mat4::from_translation(translation)
-                        }
-                        PropertyValue::MorphTargetWeights(_) => unimplemented!(),
-                    }
+    /// Switches to `asset`, a new version of the asset of the model, keeping
+    /// its placement, its scene if still there and its playback if the
+    /// clip count didn't change
+    pub(super) fn set_asset(&mut self, asset: Rc<ModelAsset>, device: &wgpu::Device) {
+        let same_clips = asset.animations.len() == self.asset.animations.len();
+        let mut model = Model::new(asset, self.transform, device);
+
+        model.index = self.index;
+        model.set_scene(self.active_scene);
+        if same_clips {
+            std::mem::swap(&mut model.animation_player, &mut self.animation_player);
+        }
+
+        *self = model;
+    }
+
+    fn update_joint_matrices(&mut self, queue: &wgpu::Queue, global_transforms: &[glam::Mat4]) {
+        if self.asset.skins.is_empty() {
+            return;
+        }
+
+        for skin in &self.asset.skins {
+            let start = skin.joint_offset as usize;
+            let end = start + skin.joints.len();
+            skin.write_joint_matrices(global_transforms, &mut self.joint_matrices[start..end]);
+        }
+
+        queue.write_buffer(
+            &self.joint_matrices_buffer,
+            0,
+            bytemuck::cast_slice(&self.joint_matrices),
+        );
+    }
+
+    /// Instances of the active scene follow their node in
+    /// `global_transforms` and morph with the `pose` weights
+    fn update_index(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+        pose: &Pose,
+        global_transforms: &[glam::Mat4],
+    ) {
+        let mesh = &self.asset.packed_primitives.per_primitives[index];
+
+        let (mesh_instances, instance_node_indices): (Vec<_>, Vec<_>) = mesh
+            .instances
+            .iter()
+            .zip(&mesh.instance_node_indices)
+            .filter(|(_, node_index)| self.scene_nodes.contains(node_index))
+            .map(|(instance, node_index)| {
+                let mut instance = *instance;
+
+                // Skinned instances are placed by their joints
+                if instance.joint_offset == InstanceData::NO_JOINTS {
+                    let index = usize::try_from(node_index.0).expect("Node index overflow");
+                    instance.transform = global_transforms[index];
                 }
 
-                mesh_instance_transforms[index] = node_transform;
-            }
+                (instance, *node_index)
+            })
+            .unzip();
 
-            if cached {
-                return;
-            }
+        if mesh_instances.is_empty() {
+            self.cached_instances[index] = None;
+            return;
+        }
+        let count = u32::try_from(mesh_instances.len()).expect("Instance count overflow");
+
+        let instances = match &mut self.cached_instances[index] {
+            Some(instances) if instances.count == count => instances,
+            cached => cached.insert(PrimitiveInstances::new(device, mesh, count)),
         };
+        queue.write_buffer(
+            &instances.transforms_buffer,
+            0,
+            bytemuck::cast_slice(&mesh_instances),
+        );
+
+        if let Some((vertex_buffer, _)) = &instances.morphed_vertices {
+            let mut morphed_vertices =
+                Vec::with_capacity(mesh.staging_vertex.len() * instance_node_indices.len());
+
+            for node_index in &instance_node_indices {
+                let node_index = usize::try_from(node_index.0).expect("Node index overflow");
+                let weights = &pose.weights[node_index];
+
+                let start = morphed_vertices.len();
+                morphed_vertices.extend_from_slice(&mesh.staging_vertex);
+                MorphTarget::apply(&mesh.morph_targets, weights, &mut morphed_vertices[start..]);
+            }
 
-        // let vertex_range = mesh.vertex_range.0 as u64..mesh.vertex_range.1 as u64;
-        // let index_range = mesh.index_range.0 as u64..mesh.index_range.1 as u64;
+            queue.write_buffer(vertex_buffer, 0, bytemuck::cast_slice(&morphed_vertices));
+        }
+    }
 
-        // TODO: FIX use global vertex buffer
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            contents: bytemuck::cast_slice(&mesh.staging_vertex),
-            label: Some("Vertex Buffer"),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let index_buffer = mesh.staging_index.as_ref().map(|indices| {
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                contents: bytemuck::cast_slice(&indices),
-                label: Some("Index Buffer"),
-                usage: wgpu::BufferUsages::INDEX,
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, delta_time: f32) {
+        let primitive_count = self.asset.packed_primitives.per_primitives.len();
+        if self.cached_instances.len() != primitive_count {
+            self.cached_instances.resize_with(primitive_count, || None)
+        }
+
+        let durations = (self.asset.animations.iter())
+            .map(|animation| animation.duration)
+            .collect::<Vec<_>>();
+        self.animation_player.advance(delta_time, &durations);
+
+        // Static models only need their rest pose once per scene
+        if !self.pose_outdated && self.asset.animations.is_empty() {
+            return;
+        }
+
+        // Paused or finished clips keep the pose already uploaded
+        let pose = self.pose(&durations);
+        if !self.pose_outdated && pose == self.pose {
+            return;
+        }
+        self.pose_outdated = false;
+
+        // Rebuild every node from its animated local transform, so that
+        // parents carry their children along, the model carrying the roots
+        let global_transforms = (self.asset.node_layout.global_transforms(&pose.transforms))
+            .into_iter()
+            .map(|global_transform| self.transform * global_transform)
+            .collect::<Vec<_>>();
+
+        self.update_joint_matrices(queue, &global_transforms);
+
+        for i in 0..primitive_count {
+            self.update_index(device, queue, i, &pose, &global_transforms);
+        }
+        self.pose = pose;
+        self.global_transforms = global_transforms;
+    }
+
+    /// Cameras of the active scene, with the node holding each of them
+    pub fn scene_cameras(&self) -> Vec<(NodeIndex, Projection)> {
+        let mut cameras = self
+            .scene_nodes
+            .iter()
+            .filter_map(|&node_index| {
+                let index = usize::try_from(node_index.0).expect("Node index overflow");
+                let node = &self.asset.node_layout.nodes[index];
+                let camera = node.camera?;
+                Some((node_index, self.asset.cameras[camera]))
             })
-        });
+            .collect::<Vec<_>>();
+        cameras.sort_by_key(|(node_index, _)| node_index.0);
 
-        let instance_transforms_buffer =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Instance Transform Buffer"),
-                contents: bytemuck::cast_slice(&mesh_instance_transforms),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
+        cameras
+    }
 
-        let color_texture = mesh.material.color_texture.map(|color_texture| {
-            let color_texture = &self.textures[color_texture.texture_index];
-            color_texture.create_bind_group(device)
-        });
+    /// Node at `index`, if the model has one
+    pub fn node(&self, index: NodeIndex) -> Option<Node<'_>> {
+        let in_range =
+            usize::try_from(index.0).is_ok_and(|i| i < self.asset.node_layout.nodes.len());
+        in_range.then(|| Node::new(self, index))
+    }
 
-        let vertex_count = if mesh.staging_index.is_some() {
-            mesh.index_range.1 - mesh.index_range.0
-        } else {
-            mesh.vertex_range.1 - mesh.vertex_range.0
-        };
-        let vertex_count = u32::try_from(vertex_count).expect("Not a valid vertex count");
+    /// First node named `name`, in node index order
+    pub fn node_by_name(&self, name: &str) -> Option<Node<'_>> {
+        let node = (self.asset.node_layout.nodes.iter())
+            .find(|node| node.name.as_deref() == Some(name))?;
+        Some(Node::new(self, node.index))
+    }
 
-        let model_render = ModelRender {
-            #[cfg(feature = "debug_gltf")]
-            metadata: self.metadata.clone(),
-            instance_transforms_buffer,
-            instance_count: mesh.instance_count,
-            vertex_buffer,
-            index_buffer,
-            color_texture,
-            vertex_count,
-        };
+    /// Root nodes of the active scene
+    pub fn scene_roots(&self) -> impl Iterator<Item = Node<'_>> {
+        (self.asset.node_layout.scenes[self.active_scene]
+            .roots
+            .iter())
+        .map(|&root| Node::new(self, root))
+    }
+
+    /// Nodes of the active scene with `key` set to `value` in their own
+    /// extras, in node index order
+    pub fn find_nodes(&self, key: &str, value: &Extras) -> Vec<NodeIndex> {
+        (self.asset.node_layout).find_nodes(&self.scene_nodes, key, value)
+    }
+
+    /// Custom properties of the active scene
+    pub fn scene_extras(&self) -> &Extras {
+        &self.asset.node_layout.scenes[self.active_scene].extras
+    }
 
-        self.cached_model_render[index] = Some(model_render);
+    /// File the asset of this model was loaded from
+    pub fn asset_path(&self) -> &Path {
+        &self.asset.path
     }
 
-    pub fn iter(&mut self, device: &wgpu::Device, start_time: &Instant) -> Vec<&ModelRender> {
-        if self.cached_model_render.len() != self.packed_primitives.per_primitives.len() {
-            self.cached_model_render
-                .resize_with(self.packed_primitives.per_primitives.len(), || None)
+    /// Names of the scenes, in scene index order
+    pub fn scene_names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.asset
+            .node_layout
+            .scenes
+            .iter()
+            .map(|scene| scene.name.as_deref())
+    }
+
+    pub fn active_scene(&self) -> usize {
+        self.active_scene
+    }
+
+    /// Only instantiates the nodes of `scene` from the next update
+    pub fn set_scene(&mut self, scene: usize) {
+        if scene >= self.asset.node_layout.scenes.len() {
+            return;
         }
 
-        for i in 0..self.packed_primitives.per_primitives.len() {
-            self.update_index(device, i, start_time);
+        self.active_scene = scene;
+        self.scene_nodes = self.asset.node_layout.scene_nodes(scene);
+        self.pose_outdated = true;
+    }
+
+    /// Blends the active clip, the clip it fades from and the layers on top
+    /// of the rest pose
+    fn pose(&self, durations: &[f32]) -> Pose {
+        let rest_pose = self.asset.node_layout.rest_pose();
+        let player = &self.animation_player;
+
+        let sample = |clip: Option<usize>, time: f32| {
+            let mut pose = rest_pose.clone();
+            if let Some(clip) = clip {
+                self.asset.animations[clip].sample(&mut pose, time);
+            }
+            pose
+        };
+
+        let active_clip = player.active_clip();
+        let duration = active_clip.map_or(0.0, |clip| durations[clip]);
+        let mut pose = sample(active_clip, player.clip_time(duration));
+
+        if let Some((fading_clip, time, factor)) = player.fading_clip(durations) {
+            let active_pose = pose;
+            pose = sample(fading_clip, time);
+            pose.blend(&active_pose, factor);
         }
 
-        self.cached_model_render.iter().flatten().collect()
+        for layer in player.layers() {
+            let time = player.layer_time(layer, durations[layer.clip]);
+            let layer_pose = sample(Some(layer.clip), time);
+
+            match layer.blend {
+                LayerBlend::Override => pose.blend(&layer_pose, layer.weight),
+                LayerBlend::Additive => pose.add(&layer_pose, &rest_pose, layer.weight),
+            }
+        }
+
+        pose
     }
-}
 
-#[derive(Debug, Clone, Copy)]
-pub enum ModelError {
-    InvalidPath,
-    InvalidGltf,
+    /// Names of the animation clips, in clip index order
+    pub fn animation_names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.asset
+            .animations
+            .iter()
+            .map(|animation| animation.name.as_deref())
+    }
+
+    /// Restarts on the first clip named `name`, returns whether there is one
+    pub fn set_active_clip_by_name(&mut self, name: &str) -> bool {
+        let clip = (self.animation_names()).position(|clip_name| clip_name == Some(name));
+        if clip.is_some() {
+            self.animation_player.set_active_clip(clip);
+        }
+
+        clip.is_some()
+    }
+
+    pub fn animation_player_mut(&mut self) -> &mut AnimationPlayer {
+        &mut self.animation_player
+    }
+
+    /// Primitives to draw, reading the vertices and indices shared by every
+    /// model of the asset unless morphed
+    pub fn iter(&self) -> impl Iterator<Item = ModelRender<'_>> {
+        let asset = &self.asset;
+        let packed_primitives = &asset.packed_primitives;
+
+        (packed_primitives.per_primitives.iter())
+            .zip(&asset.color_textures)
+            .zip(&self.cached_instances)
+            .filter_map(move |((mesh, color_texture), instances)| {
+                let instances = instances.as_ref()?;
+
+                let (vertices, morphed_vertex_count) = match &instances.morphed_vertices {
+                    Some((vertex_buffer, vertex_count)) => {
+                        (vertex_buffer.slice(..), Some(*vertex_count))
+                    }
+                    None => {
+                        let vertex_range = buffer_range::<PrimitiveVertex>(mesh.vertex_range)?;
+                        (packed_primitives.vertex_buffer.slice(vertex_range), None)
+                    }
+                };
+                let indices = match mesh.staging_index {
+                    Some(_) => {
+                        let index_range = buffer_range::<u32>(mesh.index_range)?;
+                        Some(packed_primitives.index_buffer.slice(index_range))
+                    }
+                    None => None,
+                };
+
+                let vertex_count = if indices.is_some() {
+                    mesh.index_range.1 - mesh.index_range.0
+                } else {
+                    mesh.vertex_range.1 - mesh.vertex_range.0
+                };
+                let vertex_count = u32::try_from(vertex_count).expect("Not a valid vertex count");
+
+                Some(ModelRender {
+                    #[cfg(feature = "debug_gltf")]
+                    metadata: &asset.metadata,
+                    instance_transforms_buffer: &instances.transforms_buffer,
+                    instance_count: instances.count,
+                    vertices,
+                    indices,
+                    topology: mesh.topology,
+                    color_texture: color_texture.as_ref(),
+                    vertex_count,
+                    morphed_vertex_count,
+                })
+            })
+    }
 
-    NoScene,
+    pub fn joint_matrices_bind_group(&self) -> &wgpu::BindGroup {
+        &self.joint_matrices_bind_group
+    }
 }
 
 type Range = (usize, usize);
 
-#[cfg(feature = "debug_gltf")]
+/// Byte range of the `T` elements in `range`, `None` when empty as buffer
+/// slices can't be
+fn buffer_range<T>((start, end): Range) -> Option<std::ops::Range<wgpu::BufferAddress>> {
+    let size = std::mem::size_of::<T>() as wgpu::BufferAddress;
+    (start < end).then(|| start as wgpu::BufferAddress * size..end as wgpu::BufferAddress * size)
+}
+
 pub struct PerPrimitiveMetadata {
     pub mesh_name: Option<String>,
 }
 
 pub struct PerPrimitive {
-    #[cfg(feature = "debug_gltf")]
     pub metadata: PerPrimitiveMetadata,
 
     id: usize,
     index_range: Range,
     vertex_range: Range,
 
-    instance_animations: Vec<Vec<Channel>>,
-    instance_transforms: Vec<glam::Mat4>,
-    instance_count: u32,
+    instances: Vec<InstanceData>,
 
     staging_index: Option<Vec<u32>>,
     staging_vertex: Vec<PrimitiveVertex>,
+    topology: wgpu::PrimitiveTopology,
+    morph_targets: Vec<MorphTarget>,
 
     material: Material,
 
-    #[cfg(feature = "debug_gltf")]
     instance_node_indices: Vec<NodeIndex>,
 }
 
-impl PerPrimitive {
-    pub fn transform_desc() -> wgpu::VertexBufferLayout<'static> {
-        use wgpu::VertexAttribute;
-        const ATTRIBUTES: [VertexAttribute; 4] = wgpu::vertex_attr_array![
-            11 => Float32x4, 12 => Float32x4, 13 => Float32x4, 14 => Float32x4
-        ];
-
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<glam::Mat4>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &ATTRIBUTES,
-        }
-    }
-}
-
 struct PackedPrimitives {
     index_buffer: wgpu::Buffer,
     vertex_buffer: wgpu::Buffer,
 
     per_primitives: Vec<PerPrimitive>,
+}
+
+static MODEL_INDEX: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
 
-    aabb: Aabb,
+/// Model parsed on the CPU, which only needs its buffers and textures
+/// uploaded to become a [ModelAsset]
+pub struct ModelData {
+    path: PathBuf,
+    #[cfg(feature = "debug_gltf")]
+    metadata: ModelMetadata,
+    samplers: Vec<SamplerKey>,
+    images: TextureImages,
+
+    node_layout: NodeLayout,
+    default_scene: usize,
+    cameras: Vec<Projection>,
+    animations: Vec<Animation>,
+    skins: Vec<Skin>,
+    joint_count: u32,
+
+    per_primitives: Vec<PerPrimitive>,
+    global_indices: Vec<u32>,
+    global_vertices: Vec<PrimitiveVertex>,
 }
 
-static mut MODEL_INDEX: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+/// Logs `diagnostic` at the level of its severity
+fn log_diagnostic(path: &Path, diagnostic: &Diagnostic) {
+    let level = match diagnostic.severity() {
+        Severity::Info => log::Level::Info,
+        Severity::Warning => log::Level::Warn,
+        Severity::Error => log::Level::Error,
+    };
+    log::log!(level, "{}: {}", path.display(), diagnostic);
+}
 
-impl Model {
-    pub fn from_bytes<P: AsRef<Path>>(
+impl ModelData {
+    /// Reads, parses and lints the model at `path`, decoding its images,
+    /// without touching the GPU. With a `cache`, a model whose files didn't
+    /// change since it was cached is read back from it, skipping the lints
+    pub async fn load<P: AsRef<Path>>(
         path: P,
-        bytes: &[u8],
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
+        cache: Option<&AssetCache>,
     ) -> Result<Self, ModelError> {
         use ModelError::*;
 
-        let (gltf, buffers, images) = gltf::import_slice(bytes).map_err(|_| InvalidGltf)?;
+        #[cfg(feature = "debug_gltf")]
+        log::info!("⏹ Loading gltf file: {:?}", path.as_ref());
+
+        let file_buffer = load_file_buffer(&path)
+            .await
+            .map_err(|_| InvalidPath(path.as_ref().to_path_buf()))?;
+
+        let cache = match cache {
+            Some(cache) => cache::content_hash(&path, &file_buffer)
+                .await
+                .map(|hash| (cache, hash)),
+            None => None,
+        };
+        if let Some((cache, hash)) = cache {
+            if let Some(data) = cache.read(path.as_ref(), hash) {
+                #[cfg(feature = "debug_gltf")]
+                log::info!("Read {:?} from the asset cache", path.as_ref());
+                return Ok(data);
+            }
+        }
+
+        let data = Self::import(&path, &file_buffer).await?;
+        if let Some((cache, hash)) = cache {
+            cache.write(path.as_ref(), hash, &data);
+        }
+
+        Ok(data)
+    }
+
+    async fn import<P: AsRef<Path>>(path: P, file_buffer: &[u8]) -> Result<Self, ModelError> {
+        let import = import::import(&path, file_buffer).await?;
+
+        let (document, buffers, _) = &import;
+        for diagnostic in validate(document, buffers) {
+            log_diagnostic(path.as_ref(), &diagnostic);
+        }
+
+        Self::from_import(&path, import)
+    }
+
+    fn from_import<P: AsRef<Path>>(
+        path: P,
+        (gltf, buffers, images): import::Import,
+    ) -> Result<Self, ModelError> {
+        use ModelError::*;
 
         if gltf.scenes().len() == 0 {
             return Err(NoScene);
         }
 
         #[cfg(feature = "debug_gltf")]
-        let metadata = ModelMetadata::new(path, &gltf);
+        let metadata = ModelMetadata::new(&path, &gltf);
+        let samplers = (gltf.samplers())
+            .map(|sampler| SamplerKey::from(&sampler))
+            .collect();
+        let node_layout = NodeLayout::from_gltf(gltf.nodes(), gltf.scenes())?;
+        let default_scene = gltf.default_scene().map_or(0, |scene| scene.index());
+        let cameras = gltf
+            .cameras()
+            .map(|camera| camera.projection().into())
+            .collect();
+        let animations = gltf
+            .animations()
+            .map(|animation| Animation::parse(&animation, &buffers))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut joint_count = 0;
+        let mut skins = Vec::with_capacity(gltf.skins().len());
+        for skin in gltf.skins() {
+            let skin = Skin::parse(&skin, joint_count, &buffers)?;
+            joint_count = joint_count
+                .checked_add(skin.joint_count())
+                .ok_or(Overflow("joint count"))?;
+            skins.push(skin);
+        }
 
-        let node_layout = NodeLayout::from_gltf(gltf.nodes(), gltf.animations(), &buffers);
         let meshes = gltf
             .meshes()
-            .map(|mesh| Mesh::parse(&node_layout, &mesh, &buffers))
-            .collect::<Vec<_>>();
+            .map(|mesh| Mesh::parse(&node_layout, &skins, &mesh, &buffers))
+            .collect::<Result<Vec<_>, _>>()?;
 
         let mut index_offset = 0;
         let mut vertex_offset = 0;
         let mut per_primitives = Vec::new();
         let mut global_indices = Vec::new();
         let mut global_vertices = Vec::new();
-        let mut textures = Vec::with_capacity(images.len());
-        let mut aabb = Aabb::ZERO;
 
         for mesh in meshes {
-            aabb = aabb.union(&mesh.aabb);
-
             for primitive in mesh.primitives.into_iter() {
                 let index_count = primitive.indices.as_ref().map(|vec| vec.len()).unwrap_or(0);
                 let vertex_count = primitive.vertices.len();
@@ -321,11 +781,10 @@ impl Model {
 
                 global_vertices.extend_from_slice(&primitive.vertices);
                 if let Some(indices) = &primitive.indices {
-                    global_indices.extend_from_slice(&indices);
+                    global_indices.extend_from_slice(indices);
                 }
 
                 let primitive = PerPrimitive {
-                    #[cfg(feature = "debug_gltf")]
                     metadata: PerPrimitiveMetadata {
                         mesh_name: mesh.name.clone(),
                     },
@@ -334,22 +793,80 @@ impl Model {
                     vertex_range,
                     staging_index: primitive.indices,
                     staging_vertex: primitive.vertices,
+                    topology: primitive.topology,
                     material: primitive.material.clone(),
-                    instance_animations: primitive.instance_animations,
-                    instance_transforms: primitive.instance_transforms,
-                    instance_count: primitive.instance_count,
+                    instances: primitive.instances,
+                    morph_targets: primitive.morph_targets,
 
-                    #[cfg(feature = "debug_gltf")]
                     instance_node_indices: primitive.instance_node_indices,
                 };
                 per_primitives.push(primitive);
             }
         }
 
-        for image in images {
-            let texture = Texture::create_texture_from_image(device, queue, &image);
-            textures.push(texture);
-        }
+        let materials = per_primitives.iter().map(|primitive| &primitive.material);
+        let images = TextureImages::new(&images, materials);
+
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            #[cfg(feature = "debug_gltf")]
+            metadata,
+            samplers,
+            images,
+
+            node_layout,
+            default_scene,
+            cameras,
+            animations,
+            skins,
+            joint_count,
+
+            per_primitives,
+            global_indices,
+            global_vertices,
+        })
+    }
+}
+
+impl ModelAsset {
+    /// Uploads the buffers and textures of `data`
+    pub fn new(data: ModelData, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let ModelData {
+            path,
+            #[cfg(feature = "debug_gltf")]
+            metadata,
+            samplers,
+            images,
+
+            node_layout,
+            default_scene,
+            cameras,
+            animations,
+            skins,
+            joint_count,
+
+            per_primitives,
+            global_indices,
+            global_vertices,
+        } = data;
+
+        let textures = Textures::new(device, queue, &images);
+        let samplers = Samplers::new(device, &samplers);
+        let color_textures = (per_primitives.iter())
+            .map(|primitive| {
+                let color_texture = primitive.material.color_texture?;
+                let sampler = samplers.get(color_texture.sampler_index);
+                let texture_uniform =
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Color Texture Uniform Buffer"),
+                        contents: bytemuck::bytes_of(&TextureUniform::from(&color_texture)),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+
+                let texture = textures.get(color_texture.texture_index, ColorSpace::Srgb);
+                Some(texture.create_bind_group(device, sampler, &texture_uniform))
+            })
+            .collect();
 
         let global_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -366,32 +883,22 @@ impl Model {
             index_buffer: global_index_buffer,
             vertex_buffer: global_vertex_buffer,
             per_primitives,
-            aabb,
         };
 
-        Ok(Model {
-            index: unsafe { MODEL_INDEX.fetch_add(1, std::sync::atomic::Ordering::Relaxed) },
+        Self {
+            path,
 
             #[cfg(feature = "debug_gltf")]
             metadata,
             packed_primitives,
-            textures,
-
-            cached_model_render: Vec::new(),
-        })
-    }
-
-    pub async fn from_path<P: AsRef<Path>>(
-        path: P,
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-    ) -> Result<Model, ModelError> {
-        use ModelError::*;
-
-        #[cfg(feature = "debug_gltf")]
-        log::info!("⏹ Loading gltf file: {:?}", path.as_ref());
-
-        let file_buffer = load_file_buffer(&path).await.map_err(|_| InvalidPath)?;
-        Self::from_bytes(&path, &file_buffer, device, queue)
+            color_textures,
+
+            node_layout,
+            default_scene,
+            cameras,
+            animations,
+            skins,
+            joint_count,
+        }
     }
 }
@@ -0,0 +1,76 @@
+use crate::render::asset_store::{
+    extras::Extras,
+    node_layout::{NodeData, NodeIndex, NodeTransform},
+    Model, PerPrimitive,
+};
+
+/// Node of a [Model], giving its hierarchy and its transforms in the pose
+/// of the last update
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    model: &'a Model,
+    index: NodeIndex,
+}
+
+impl<'a> Node<'a> {
+    /// `index` must be a node of `model`
+    pub(super) fn new(model: &'a Model, index: NodeIndex) -> Self {
+        Self { model, index }
+    }
+
+    pub fn index(&self) -> NodeIndex {
+        self.index
+    }
+
+    fn data(&self) -> &'a NodeData {
+        let index = usize::try_from(self.index.0).expect("Node index overflow");
+        &self.model.asset.node_layout.nodes[index]
+    }
+
+    pub fn name(&self) -> Option<&'a str> {
+        self.data().name.as_deref()
+    }
+
+    /// Custom properties of the node itself, not inherited from its mesh
+    pub fn extras(&self) -> &'a Extras {
+        &self.data().extras
+    }
+
+    pub fn parent(&self) -> Option<Node<'a>> {
+        let model = self.model;
+        self.data().parent.map(|parent| Node::new(model, parent))
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> {
+        let model = self.model;
+        (self.data().children_index.iter()).map(move |&child| Node::new(model, child))
+    }
+
+    /// Primitives of the mesh instantiated by the node
+    fn primitives(&self) -> impl Iterator<Item = &'a PerPrimitive> {
+        let index = self.index;
+        (self.model.asset.packed_primitives.per_primitives.iter())
+            .filter(move |primitive| primitive.instance_node_indices.contains(&index))
+    }
+
+    pub fn mesh_name(&self) -> Option<&'a str> {
+        let primitive = self.primitives().next()?;
+        primitive.metadata.mesh_name.as_deref()
+    }
+
+    /// Names of the materials of the node mesh, one per primitive
+    pub fn material_names(&self) -> impl Iterator<Item = Option<&'a str>> {
+        self.primitives()
+            .map(|primitive| primitive.material.name.as_deref())
+    }
+
+    pub fn local_transform(&self) -> NodeTransform {
+        let index = usize::try_from(self.index.0).expect("Node index overflow");
+        self.model.pose.transforms[index]
+    }
+
+    pub fn world_transform(&self) -> glam::Mat4 {
+        let index = usize::try_from(self.index.0).expect("Node index overflow");
+        self.model.global_transforms[index]
+    }
+}
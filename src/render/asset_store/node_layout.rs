@@ -1,23 +1,60 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::render::asset_store::animation::{Animation, Channel};
+use crate::render::asset_store::{
+    cache::{Cached, Reader},
+    error::{to_u32, ModelError},
+    extras::{has_extra, parse_extras, Extras},
+    pose::Pose,
+};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MeshIndex(pub u32);
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct NodeIndex(pub u32);
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SkinIndex(pub u32);
+
+/// Local transform of a node, kept decomposed so that animation channels
+/// can replace each component independently
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NodeTransform {
+    pub translation: glam::Vec3,
+    pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+}
+
+impl NodeTransform {
+    pub fn matrix(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl From<gltf::scene::Transform> for NodeTransform {
+    fn from(transform: gltf::scene::Transform) -> Self {
+        let (translation, rotation, scale) = transform.decomposed();
+
+        Self {
+            translation: glam::Vec3::from_array(translation),
+            rotation: glam::Quat::from_array(rotation),
+            scale: glam::Vec3::from_array(scale),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub(super) struct NodeData {
-    #[cfg(feature = "debug_gltf")]
-    name: Option<String>,
+    pub(super) name: Option<String>,
     pub(super) index: NodeIndex,
-    transform_local: glam::Mat4,
+    transform_local: NodeTransform,
     transform_global: glam::Mat4,
-    parent: Option<NodeIndex>,
-    children_index: Vec<NodeIndex>,
-
-    animation_channel: Vec<Channel>,
+    pub(super) parent: Option<NodeIndex>,
+    pub(super) children_index: Vec<NodeIndex>,
+    pub(super) skin: Option<SkinIndex>,
+    /// glTF camera attached to the node
+    pub(super) camera: Option<usize>,
+    /// Morph target weights, from the node or its mesh defaults
+    weights: Vec<f32>,
+    pub(super) extras: Extras,
 }
 
 impl std::fmt::Debug for NodeData {
@@ -28,11 +65,13 @@ impl std::fmt::Debug for NodeData {
         let mut debug_struct = f.debug_struct(title);
 
         debug_struct.field("index", &self.index.0);
-        #[cfg(feature = "debug_gltf")]
         debug_struct.field("name", &self.name.as_ref().unwrap_or(&String::from("None")));
         if let Some(parent) = self.parent {
             debug_struct.field("parent", &parent.0);
         }
+        if !self.extras.is_null() {
+            debug_struct.field("extras", &format!("{}", self.extras));
+        }
 
         let matrix = gltf::scene::Transform::Matrix {
             matrix: self.transform_global.to_cols_array_2d(),
@@ -47,81 +86,113 @@ impl std::fmt::Debug for NodeData {
     }
 }
 
+#[derive(Debug, Clone)]
+pub(super) struct SceneData {
+    pub(super) name: Option<String>,
+    pub(super) roots: Vec<NodeIndex>,
+    pub(super) extras: Extras,
+}
+
 pub struct NodeLayout {
     pub(super) mesh_nodes: HashMap<MeshIndex, Vec<NodeIndex>>,
     pub(super) node_mesh: HashMap<NodeIndex, MeshIndex>,
+    /// Extras of the meshes instantiated by a node
+    pub(super) mesh_extras: HashMap<MeshIndex, Extras>,
     pub(super) nodes: Vec<NodeData>,
+    pub(super) scenes: Vec<SceneData>,
 }
 
 impl NodeLayout {
     pub fn from_gltf(
         gltf_nodes: gltf::iter::Nodes,
-        gltf_animation: gltf::iter::Animations,
-        buffers: &[gltf::buffer::Data],
-    ) -> Self {
+        gltf_scenes: gltf::iter::Scenes,
+    ) -> Result<Self, ModelError> {
         let mut mesh_nodes = HashMap::<_, Vec<_>>::new();
         let mut node_mesh = HashMap::new();
+        let mut mesh_extras = HashMap::new();
         let mut nodes = Vec::new();
         let mut parent = HashMap::new();
 
         for node in gltf_nodes {
-            let node_index = u32::try_from(node.index()).expect("Node index overflow");
-            let node_index = NodeIndex(node_index);
+            let node_index = NodeIndex(to_u32(node.index(), "node index")?);
             let transform_matrix = node.transform().matrix();
 
-            let transform_local = glam::Mat4::from_cols_array_2d(&transform_matrix);
-            let transform_global = transform_local;
+            let transform_local = NodeTransform::from(node.transform());
+            let transform_global = glam::Mat4::from_cols_array_2d(&transform_matrix);
 
             if let Some(mesh) = node.mesh() {
-                let mesh_index = u32::try_from(mesh.index()).expect("Mesh index overflow");
+                let mesh_index = to_u32(mesh.index(), "mesh index")?;
                 mesh_nodes
                     .entry(MeshIndex(mesh_index))
                     .or_default()
                     .push(node_index);
                 node_mesh.insert(node_index, MeshIndex(mesh_index));
+                mesh_extras
+                    .entry(MeshIndex(mesh_index))
+                    .or_insert_with(|| parse_extras(mesh.extras()));
             }
 
             let mut children_index = Vec::new();
             for child in node.children() {
-                let child_index = u32::try_from(child.index()).expect("Child index overflow");
-                let child_index = NodeIndex(child_index);
+                let child_index = NodeIndex(to_u32(child.index(), "node index")?);
                 children_index.push(child_index);
-                parent.insert(child_index, node_index);
+                if parent.insert(child_index, node_index).is_some() {
+                    return Err(ModelError::InvalidNode {
+                        node: child.index(),
+                        reason: "child of several nodes",
+                    });
+                }
             }
 
+            let skin = match node.skin() {
+                Some(skin) => Some(SkinIndex(to_u32(skin.index(), "skin index")?)),
+                None => None,
+            };
+
+            let mesh_weights = node.mesh().and_then(|mesh| mesh.weights());
+            let weights = node.weights().or(mesh_weights).unwrap_or_default();
+
             nodes.push(NodeData {
-                #[cfg(feature = "debug_gltf")]
                 name: node.name().map(ToOwned::to_owned),
                 index: node_index,
                 transform_local,
                 transform_global,
                 parent: None,
                 children_index,
-
-                animation_channel: Vec::new(),
+                skin,
+                camera: node.camera().map(|camera| camera.index()),
+                weights: weights.to_vec(),
+                extras: parse_extras(node.extras()),
             });
         }
 
         for node in &mut nodes {
             node.parent = parent.get(&node.index).copied();
         }
+        check_hierarchy(&nodes)?;
 
-        let animations = gltf_animation
-            .map(|animation| Animation::parse(&animation, &buffers))
-            .collect::<Vec<_>>();
+        let scenes = gltf_scenes
+            .map(|scene| {
+                let roots = scene
+                    .nodes()
+                    .map(|node| Ok(NodeIndex(to_u32(node.index(), "node index")?)))
+                    .collect::<Result<_, _>>()?;
 
-        for animation in animations {
-            for channel in animation.channels {
-                let index = usize::try_from(channel.node_index.0).expect("Child index overflow");
-                nodes[index].animation_channel.push(channel);
-            }
-        }
+                Ok(SceneData {
+                    name: scene.name().map(ToOwned::to_owned),
+                    roots,
+                    extras: parse_extras(scene.extras()),
+                })
+            })
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             mesh_nodes,
             node_mesh,
+            mesh_extras,
             nodes,
-        }
+            scenes,
+        })
     }
 
     pub fn get_node_transform(&self, node_index: NodeIndex) -> glam::Mat4 {
@@ -133,14 +204,278 @@ impl NodeLayout {
             let parent_index = usize::try_from(parent_index.0).expect("Node index overflow");
             let parent = &self.nodes[parent_index];
             transform = parent.transform_global * transform;
-            index = usize::try_from(parent_index).expect("Parent node index overflow");
+            index = parent_index;
         }
 
         transform
     }
 
-    pub fn get_node_animations(&self, node_index: NodeIndex) -> &Vec<Channel> {
+    /// Nodes reachable from the roots of `scene`
+    pub fn scene_nodes(&self, scene: usize) -> HashSet<NodeIndex> {
+        let mut scene_nodes = HashSet::new();
+        let mut stack = self.scenes[scene].roots.clone();
+
+        while let Some(node_index) = stack.pop() {
+            if !scene_nodes.insert(node_index) {
+                continue;
+            }
+
+            let index = usize::try_from(node_index.0).expect("Node index overflow");
+            stack.extend_from_slice(&self.nodes[index].children_index);
+        }
+
+        scene_nodes
+    }
+
+    /// Nodes among `nodes` with `key` set to `value` in their extras, in
+    /// node index order
+    pub fn find_nodes(
+        &self,
+        nodes: &HashSet<NodeIndex>,
+        key: &str,
+        value: &Extras,
+    ) -> Vec<NodeIndex> {
+        let mut found = (nodes.iter())
+            .filter(|node_index| {
+                let index = usize::try_from(node_index.0).expect("Node index overflow");
+                has_extra(&self.nodes[index].extras, key, value)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+
+        found
+    }
+
+    /// Static transforms and weights of every node, before any animation
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            transforms: self.nodes.iter().map(|node| node.transform_local).collect(),
+            weights: self.nodes.iter().map(|node| node.weights.clone()).collect(),
+        }
+    }
+
+    /// World transforms of every node, propagating `local_transforms`
+    /// from the roots down to the leaves
+    pub fn global_transforms(&self, local_transforms: &[NodeTransform]) -> Vec<glam::Mat4> {
+        let mut global_transforms = vec![glam::Mat4::IDENTITY; self.nodes.len()];
+
+        let mut stack = self
+            .nodes
+            .iter()
+            .filter(|node| node.parent.is_none())
+            .map(|node| (node.index, glam::Mat4::IDENTITY))
+            .collect::<Vec<_>>();
+
+        while let Some((node_index, parent_transform)) = stack.pop() {
+            let index = usize::try_from(node_index.0).expect("Node index overflow");
+            let transform = parent_transform * local_transforms[index].matrix();
+            global_transforms[index] = transform;
+
+            for child_index in &self.nodes[index].children_index {
+                stack.push((*child_index, transform));
+            }
+        }
+
+        global_transforms
+    }
+}
+
+/// Nodes with a single parent can still form cycles, which are out of
+/// reach from the roots, and would make the traversals loop forever
+fn check_hierarchy(nodes: &[NodeData]) -> Result<(), ModelError> {
+    let mut reached = vec![false; nodes.len()];
+    let mut stack = (nodes.iter())
+        .filter(|node| node.parent.is_none())
+        .map(|node| node.index)
+        .collect::<Vec<_>>();
+
+    while let Some(node_index) = stack.pop() {
         let index = usize::try_from(node_index.0).expect("Node index overflow");
-        &self.nodes[index].animation_channel
+        reached[index] = true;
+        stack.extend_from_slice(&nodes[index].children_index);
+    }
+
+    match reached.iter().position(|&reached| !reached) {
+        Some(node) => Err(ModelError::InvalidNode {
+            node,
+            reason: "part of a cycle",
+        }),
+        None => Ok(()),
+    }
+}
+
+macro_rules! cached_index {
+    ($($index:ident),*) => {
+        $(
+            impl Cached for $index {
+                fn write(&self, writer: &mut Vec<u8>) {
+                    self.0.write(writer);
+                }
+
+                fn read(reader: &mut Reader) -> Option<Self> {
+                    Some(Self(u32::read(reader)?))
+                }
+            }
+        )*
+    };
+}
+
+cached_index!(MeshIndex, NodeIndex, SkinIndex);
+
+impl Cached for NodeTransform {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.translation.write(writer);
+        self.rotation.write(writer);
+        self.scale.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            translation: Cached::read(reader)?,
+            rotation: Cached::read(reader)?,
+            scale: Cached::read(reader)?,
+        })
+    }
+}
+
+impl Cached for NodeData {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.name.write(writer);
+        self.index.write(writer);
+        self.transform_local.write(writer);
+        self.transform_global.write(writer);
+        self.parent.write(writer);
+        self.children_index.write(writer);
+        self.skin.write(writer);
+        self.camera.write(writer);
+        self.weights.write(writer);
+        self.extras.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            name: Cached::read(reader)?,
+            index: Cached::read(reader)?,
+            transform_local: Cached::read(reader)?,
+            transform_global: Cached::read(reader)?,
+            parent: Cached::read(reader)?,
+            children_index: Cached::read(reader)?,
+            skin: Cached::read(reader)?,
+            camera: Cached::read(reader)?,
+            weights: Cached::read(reader)?,
+            extras: Cached::read(reader)?,
+        })
+    }
+}
+
+impl Cached for SceneData {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.name.write(writer);
+        self.roots.write(writer);
+        self.extras.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            name: Cached::read(reader)?,
+            roots: Cached::read(reader)?,
+            extras: Cached::read(reader)?,
+        })
+    }
+}
+
+impl Cached for NodeLayout {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.mesh_nodes.write(writer);
+        self.node_mesh.write(writer);
+        self.mesh_extras.write(writer);
+        self.nodes.write(writer);
+        self.scenes.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            mesh_nodes: Cached::read(reader)?,
+            node_mesh: Cached::read(reader)?,
+            mesh_extras: Cached::read(reader)?,
+            nodes: Cached::read(reader)?,
+            scenes: Cached::read(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(nodes: serde_json::Value) -> Result<NodeLayout, ModelError> {
+        let json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "nodes": nodes,
+            "scenes": [{ "nodes": [0] }],
+        });
+        let gltf = gltf::Gltf::from_slice(json.to_string().as_bytes()).expect("Invalid test glTF");
+
+        NodeLayout::from_gltf(gltf.nodes(), gltf.scenes())
+    }
+
+    #[test]
+    fn trees_are_accepted() {
+        let layout =
+            parse(serde_json::json!([{ "children": [1, 2] }, {}, { "children": [3] }, {}]));
+
+        assert_eq!(layout.unwrap().scene_nodes(0).len(), 4);
+    }
+
+    #[test]
+    fn nodes_are_found_by_their_own_extras() {
+        let json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "meshes": [{ "primitives": [], "extras": { "spawn": true } }],
+            "nodes": [
+                { "children": [1, 2, 3], "extras": { "checkpoint": 1 } },
+                { "extras": { "spawn": true } },
+                { "mesh": 0 },
+                { "extras": { "spawn": false } },
+                { "extras": { "spawn": true } }
+            ],
+            "scenes": [{ "nodes": [0], "extras": { "spawn": true } }, { "nodes": [4] }],
+        });
+        let gltf = gltf::Gltf::from_slice(json.to_string().as_bytes()).expect("Invalid test glTF");
+        let layout = NodeLayout::from_gltf(gltf.nodes(), gltf.scenes()).unwrap();
+
+        let scene_nodes = layout.scene_nodes(0);
+        let find = |key, value| layout.find_nodes(&scene_nodes, key, &value);
+        assert_eq!(find("spawn", true.into()), [NodeIndex(1)]);
+        assert_eq!(find("spawn", false.into()), [NodeIndex(3)]);
+        assert_eq!(find("checkpoint", 1.into()), [NodeIndex(0)]);
+        assert!(find("checkpoint", 2.into()).is_empty());
+        assert_eq!(layout.scenes[0].extras["spawn"], true);
+    }
+
+    #[test]
+    fn nodes_with_several_parents_are_rejected() {
+        let layout = parse(serde_json::json!([{ "children": [1, 2] }, {}, { "children": [1] }]));
+
+        assert!(matches!(
+            layout,
+            Err(ModelError::InvalidNode { node: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let layout = parse(serde_json::json!([{}, { "children": [2] }, { "children": [1] }]));
+        assert!(matches!(
+            layout,
+            Err(ModelError::InvalidNode { node: 1, .. })
+        ));
+
+        let layout = parse(serde_json::json!([{ "children": [0] }]));
+        assert!(matches!(
+            layout,
+            Err(ModelError::InvalidNode { node: 0, .. })
+        ));
     }
 }
@@ -0,0 +1,162 @@
+use crate::render::asset_store::node_layout::NodeTransform;
+
+/// Local transform and morph target weights of every node of a model,
+/// indexed by [NodeIndex](super::NodeIndex)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose {
+    pub transforms: Vec<NodeTransform>,
+    pub weights: Vec<Vec<f32>>,
+}
+
+impl Pose {
+    /// Moves `self` towards `other` by `factor`, using spherical
+    /// interpolation for rotations
+    pub fn blend(&mut self, other: &Pose, factor: f32) {
+        for (transform, other) in self.transforms.iter_mut().zip(&other.transforms) {
+            transform.translation = transform.translation.lerp(other.translation, factor);
+            transform.rotation = transform.rotation.slerp(other.rotation, factor);
+            transform.scale = transform.scale.lerp(other.scale, factor);
+        }
+
+        for (weights, other) in self.weights.iter_mut().zip(&other.weights) {
+            for (weight, other) in weights.iter_mut().zip(other) {
+                *weight += (other - *weight) * factor;
+            }
+        }
+    }
+
+    /// Adds the difference between `additive` and `reference` on top of
+    /// `self`, scaled by `factor`
+    pub fn add(&mut self, additive: &Pose, reference: &Pose, factor: f32) {
+        let transforms = self
+            .transforms
+            .iter_mut()
+            .zip(&additive.transforms)
+            .zip(&reference.transforms);
+
+        for ((transform, additive), reference) in transforms {
+            let translation = additive.translation - reference.translation;
+            transform.translation += translation * factor;
+
+            let rotation = reference.rotation.inverse() * additive.rotation;
+            let rotation = glam::Quat::IDENTITY.slerp(rotation, factor);
+            transform.rotation = (transform.rotation * rotation).normalize();
+
+            // Collapsed reference scales can't be divided by, leave them be
+            let collapsed = reference.scale.cmpeq(glam::Vec3::ZERO);
+            let scale =
+                glam::Vec3::select(collapsed, glam::Vec3::ONE, additive.scale / reference.scale);
+            transform.scale *= glam::Vec3::ONE.lerp(scale, factor);
+        }
+
+        let weights = self
+            .weights
+            .iter_mut()
+            .zip(&additive.weights)
+            .zip(&reference.weights);
+
+        for ((weights, additive), reference) in weights {
+            for ((weight, additive), reference) in weights.iter_mut().zip(additive).zip(reference) {
+                *weight += (additive - reference) * factor;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+
+    use super::*;
+
+    fn pose(translation: Vec3, rotation: Quat, scale: Vec3, weight: f32) -> Pose {
+        Pose {
+            transforms: vec![NodeTransform {
+                translation,
+                rotation,
+                scale,
+            }],
+            weights: vec![vec![weight]],
+        }
+    }
+
+    fn rest() -> Pose {
+        pose(Vec3::ZERO, Quat::IDENTITY, Vec3::ONE, 0.0)
+    }
+
+    #[test]
+    fn blend_moves_towards_the_other_pose() {
+        let mut blended = rest();
+        let other = pose(
+            Vec3::new(2.0, 0.0, 0.0),
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            Vec3::splat(3.0),
+            1.0,
+        );
+
+        blended.blend(&other, 0.5);
+
+        let transform = blended.transforms[0];
+        assert!(transform
+            .translation
+            .abs_diff_eq(Vec3::new(1.0, 0.0, 0.0), 1e-6));
+        let expected = Quat::from_rotation_y(std::f32::consts::FRAC_PI_4);
+        assert!(transform.rotation.abs_diff_eq(expected, 1e-6));
+        assert!(transform.scale.abs_diff_eq(Vec3::splat(2.0), 1e-6));
+        assert_eq!(blended.weights[0][0], 0.5);
+    }
+
+    #[test]
+    fn add_applies_the_difference_to_the_reference() {
+        let base = pose(
+            Vec3::new(1.0, 1.0, 1.0),
+            Quat::from_rotation_x(0.5),
+            Vec3::splat(2.0),
+            0.25,
+        );
+        let reference = pose(Vec3::new(0.0, 1.0, 0.0), Quat::IDENTITY, Vec3::ONE, 0.5);
+        let additive = pose(
+            Vec3::new(2.0, 1.0, 0.0),
+            Quat::from_rotation_x(0.25),
+            Vec3::splat(3.0),
+            1.0,
+        );
+
+        let mut full = base.clone();
+        full.add(&additive, &reference, 1.0);
+        let transform = full.transforms[0];
+        assert!(transform
+            .translation
+            .abs_diff_eq(Vec3::new(3.0, 1.0, 1.0), 1e-6));
+        assert!(transform
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_x(0.75), 1e-6));
+        assert!(transform.scale.abs_diff_eq(Vec3::splat(6.0), 1e-6));
+        assert_eq!(full.weights[0][0], 0.75);
+
+        let mut half = base.clone();
+        half.add(&additive, &reference, 0.5);
+        let transform = half.transforms[0];
+        assert!(transform
+            .translation
+            .abs_diff_eq(Vec3::new(2.0, 1.0, 1.0), 1e-6));
+        assert!(transform
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_x(0.625), 1e-6));
+        assert!(transform.scale.abs_diff_eq(Vec3::splat(4.0), 1e-6));
+        assert_eq!(half.weights[0][0], 0.5);
+    }
+
+    #[test]
+    fn add_keeps_scales_of_collapsed_references() {
+        let mut posed = rest();
+        let reference = pose(Vec3::ZERO, Quat::IDENTITY, Vec3::new(0.0, 1.0, 1.0), 0.0);
+        let additive = pose(Vec3::ZERO, Quat::IDENTITY, Vec3::splat(2.0), 0.0);
+
+        posed.add(&additive, &reference, 1.0);
+
+        let scale = posed.transforms[0].scale;
+        assert!(scale.abs_diff_eq(Vec3::new(1.0, 2.0, 2.0), 1e-6));
+        assert!(scale.is_finite());
+    }
+}
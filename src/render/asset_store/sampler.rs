@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+use crate::render::asset_store::cache::{Cached, Reader};
+
+/// Filtering and wrapping of a glTF sampler, textures without one use the
+/// [Default] repeat and linear filtering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerKey {
+    address_mode_u: wgpu::AddressMode,
+    address_mode_v: wgpu::AddressMode,
+    mag_filter: wgpu::FilterMode,
+    min_filter: wgpu::FilterMode,
+    mipmap_filter: wgpu::FilterMode,
+    /// glTF min filters without mipmaps only read the full size level
+    mipmaps: bool,
+}
+
+impl Default for SamplerKey {
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            mipmaps: true,
+        }
+    }
+}
+
+impl From<&gltf::texture::Sampler<'_>> for SamplerKey {
+    fn from(sampler: &gltf::texture::Sampler) -> Self {
+        let default = Self::default();
+
+        let mag_filter = match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+            Some(MagFilter::Linear) => wgpu::FilterMode::Linear,
+            None => default.mag_filter,
+        };
+
+        use wgpu::FilterMode::{Linear, Nearest};
+        let (min_filter, mipmap_filter, mipmaps) = match sampler.min_filter() {
+            Some(MinFilter::Nearest) => (Nearest, Nearest, false),
+            Some(MinFilter::Linear) => (Linear, Nearest, false),
+            Some(MinFilter::NearestMipmapNearest) => (Nearest, Nearest, true),
+            Some(MinFilter::LinearMipmapNearest) => (Linear, Nearest, true),
+            Some(MinFilter::NearestMipmapLinear) => (Nearest, Linear, true),
+            Some(MinFilter::LinearMipmapLinear) => (Linear, Linear, true),
+            None => (default.min_filter, default.mipmap_filter, default.mipmaps),
+        };
+
+        Self {
+            address_mode_u: address_mode(sampler.wrap_s()),
+            address_mode_v: address_mode(sampler.wrap_t()),
+            mag_filter,
+            min_filter,
+            mipmap_filter,
+            mipmaps,
+        }
+    }
+}
+
+fn address_mode(wrapping_mode: WrappingMode) -> wgpu::AddressMode {
+    match wrapping_mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    }
+}
+
+impl Cached for wgpu::AddressMode {
+    fn write(&self, writer: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            wgpu::AddressMode::ClampToEdge => 0,
+            wgpu::AddressMode::Repeat => 1,
+            wgpu::AddressMode::MirrorRepeat => 2,
+            wgpu::AddressMode::ClampToBorder => 3,
+        };
+        tag.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        match u8::read(reader)? {
+            0 => Some(wgpu::AddressMode::ClampToEdge),
+            1 => Some(wgpu::AddressMode::Repeat),
+            2 => Some(wgpu::AddressMode::MirrorRepeat),
+            3 => Some(wgpu::AddressMode::ClampToBorder),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for wgpu::FilterMode {
+    fn write(&self, writer: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            wgpu::FilterMode::Nearest => 0,
+            wgpu::FilterMode::Linear => 1,
+        };
+        tag.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        match u8::read(reader)? {
+            0 => Some(wgpu::FilterMode::Nearest),
+            1 => Some(wgpu::FilterMode::Linear),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for SamplerKey {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.address_mode_u.write(writer);
+        self.address_mode_v.write(writer);
+        self.mag_filter.write(writer);
+        self.min_filter.write(writer);
+        self.mipmap_filter.write(writer);
+        self.mipmaps.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            address_mode_u: Cached::read(reader)?,
+            address_mode_v: Cached::read(reader)?,
+            mag_filter: Cached::read(reader)?,
+            min_filter: Cached::read(reader)?,
+            mipmap_filter: Cached::read(reader)?,
+            mipmaps: Cached::read(reader)?,
+        })
+    }
+}
+
+/// wgpu samplers of a model, glTF samplers sharing the same settings share
+/// the same wgpu sampler
+pub struct Samplers {
+    samplers: Vec<wgpu::Sampler>,
+    /// Index in `samplers` of each glTF sampler
+    gltf_samplers: Vec<usize>,
+    default_sampler: usize,
+}
+
+impl Samplers {
+    pub fn new(device: &wgpu::Device, gltf_samplers: &[SamplerKey]) -> Self {
+        let mut samplers = Vec::new();
+        let mut sampler_indices = HashMap::new();
+
+        let mut get_or_create = |key: SamplerKey| {
+            *sampler_indices.entry(key).or_insert_with(|| {
+                let mut descriptor = wgpu::SamplerDescriptor {
+                    label: Some("Texture Sampler"),
+                    address_mode_u: key.address_mode_u,
+                    address_mode_v: key.address_mode_v,
+                    address_mode_w: wgpu::AddressMode::Repeat,
+                    mag_filter: key.mag_filter,
+                    min_filter: key.min_filter,
+                    mipmap_filter: key.mipmap_filter,
+                    ..Default::default()
+                };
+                if !key.mipmaps {
+                    descriptor.lod_max_clamp = 0.0;
+                }
+                samplers.push(device.create_sampler(&descriptor));
+                samplers.len() - 1
+            })
+        };
+
+        let gltf_samplers = gltf_samplers
+            .iter()
+            .map(|&key| get_or_create(key))
+            .collect();
+        let default_sampler = get_or_create(SamplerKey::default());
+
+        #[cfg(feature = "debug_gltf")]
+        log::info!("{} distinct texture samplers", samplers.len());
+
+        Self {
+            samplers,
+            gltf_samplers,
+            default_sampler,
+        }
+    }
+
+    /// Sampler of the glTF sampler `sampler_index`, or the default one
+    pub fn get(&self, sampler_index: Option<usize>) -> &wgpu::Sampler {
+        let index = sampler_index.map_or(self.default_sampler, |sampler_index| {
+            self.gltf_samplers[sampler_index]
+        });
+
+        &self.samplers[index]
+    }
+}
@@ -0,0 +1,120 @@
+use crate::render::asset_store::{
+    cache::{Cached, Reader},
+    error::{to_u32, ModelError},
+    NodeIndex,
+};
+
+const JOINT_MATRICES_BIND_GROUP_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor<'static> =
+    wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+        label: Some("Joint Matrices Bind Group Layout"),
+    };
+
+/// Layouts created from the same descriptor are compatible, so each model
+/// can build its bind group without sharing the pipeline one
+pub fn joint_matrices_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&JOINT_MATRICES_BIND_GROUP_LAYOUT_DESCRIPTOR)
+}
+
+pub struct Skin {
+    /// Offset of the first joint of this skin in the model joint matrices buffer
+    pub joint_offset: u32,
+    pub joints: Vec<NodeIndex>,
+    pub inverse_bind_matrices: Vec<glam::Mat4>,
+}
+
+impl Skin {
+    pub fn parse(
+        skin: &gltf::Skin,
+        joint_offset: u32,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Self, ModelError> {
+        #[cfg(feature = "debug_gltf")]
+        log::info!(
+            "Skin#{} ({}) with {} joints",
+            skin.index(),
+            skin.name().unwrap_or("None"),
+            skin.joints().count()
+        );
+
+        let joints = skin
+            .joints()
+            .map(|joint| Ok(NodeIndex(to_u32(joint.index(), "joint index")?)))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Checked once so that `joint_count` can't overflow
+        to_u32(joints.len(), "joint count")?;
+
+        // Inverse bind matrices default to identity when not provided
+        // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#skins-overview
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        let inverse_bind_matrices = reader
+            .read_inverse_bind_matrices()
+            .map(|matrices| {
+                matrices
+                    .map(|matrix| glam::Mat4::from_cols_array_2d(&matrix))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_else(|| vec![glam::Mat4::IDENTITY; joints.len()]);
+
+        if inverse_bind_matrices.len() < joints.len() {
+            return Err(ModelError::InvalidSkin {
+                skin: skin.index(),
+                accessor: skin
+                    .inverse_bind_matrices()
+                    .map(|accessor| accessor.index()),
+                reason: "fewer inverse bind matrices than joints",
+            });
+        }
+
+        Ok(Self {
+            joint_offset,
+            joints,
+            inverse_bind_matrices,
+        })
+    }
+
+    pub fn joint_count(&self) -> u32 {
+        u32::try_from(self.joints.len()).expect("Joint count overflow")
+    }
+
+    /// Writes `global_joint_transform * inverse_bind_matrix` for each joint.
+    ///
+    /// The transform of the skinned mesh node itself is ignored, as the
+    /// specification requires.
+    pub fn write_joint_matrices(&self, global_transforms: &[glam::Mat4], out: &mut [glam::Mat4]) {
+        for (i, (joint, inverse_bind_matrix)) in self
+            .joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .enumerate()
+        {
+            let joint_index = usize::try_from(joint.0).expect("Joint index overflow");
+            out[i] = global_transforms[joint_index] * *inverse_bind_matrix;
+        }
+    }
+}
+
+impl Cached for Skin {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.joint_offset.write(writer);
+        self.joints.write(writer);
+        self.inverse_bind_matrices.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            joint_offset: Cached::read(reader)?,
+            joints: Cached::read(reader)?,
+            inverse_bind_matrices: Cached::read(reader)?,
+        })
+    }
+}
@@ -0,0 +1,139 @@
+use crate::render::{
+    asset_store::cache::{write_pod_slice, Cached, Reader},
+    texture::{ColorSpace, MipChain, Texture},
+};
+
+use super::material::Material;
+
+/// Images of a model expanded to the color space of the material slots using
+/// them, twice when used by both sRGB and linear slots, with their mip levels
+pub struct TextureImages {
+    srgb: Vec<Option<MipChain>>,
+    linear: Vec<Option<MipChain>>,
+}
+
+impl TextureImages {
+    pub fn new<'a>(
+        images: &[gltf::image::Data],
+        materials: impl IntoIterator<Item = &'a Material>,
+    ) -> Self {
+        let mut srgb = Vec::new();
+        srgb.resize_with(images.len(), || None);
+        let mut linear = Vec::new();
+        linear.resize_with(images.len(), || None);
+
+        for (texture_info, color_space) in materials.into_iter().flat_map(Material::textures) {
+            let mip_chains = match color_space {
+                ColorSpace::Srgb => &mut srgb,
+                ColorSpace::Linear => &mut linear,
+            };
+
+            let image_index = texture_info.texture_index;
+            mip_chains[image_index]
+                .get_or_insert_with(|| MipChain::new(&images[image_index], color_space));
+        }
+
+        Self { srgb, linear }
+    }
+}
+
+/// Only the formats [MipChain::new] decodes to are cached
+impl Cached for MipChain {
+    fn write(&self, writer: &mut Vec<u8>) {
+        let tag: u8 = match self.format {
+            wgpu::TextureFormat::Rgba8UnormSrgb => 0,
+            wgpu::TextureFormat::Rgba8Unorm => 1,
+            wgpu::TextureFormat::Rgba16Float => 2,
+            format => unreachable!("Mip chain of format {:?}", format),
+        };
+        tag.write(writer);
+
+        self.levels.len().write(writer);
+        for (width, height, pixels) in &self.levels {
+            width.write(writer);
+            height.write(writer);
+            write_pod_slice(writer, pixels);
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        let format = match u8::read(reader)? {
+            0 => wgpu::TextureFormat::Rgba8UnormSrgb,
+            1 => wgpu::TextureFormat::Rgba8Unorm,
+            2 => wgpu::TextureFormat::Rgba16Float,
+            _ => return None,
+        };
+
+        let level_count = usize::read(reader)?;
+        let mut levels = Vec::with_capacity(level_count.min(32));
+        for _ in 0..level_count {
+            let (width, height) = (u32::read(reader)?, u32::read(reader)?);
+            let pixels: Vec<u8> = reader.read_pod_vec()?;
+            let bytes_per_pixel = format.block_size(None)? as usize;
+            if pixels.len() != width as usize * height as usize * bytes_per_pixel {
+                return None;
+            }
+            levels.push((width, height, pixels));
+        }
+
+        (!levels.is_empty()).then_some(Self { format, levels })
+    }
+}
+
+impl Cached for TextureImages {
+    fn write(&self, writer: &mut Vec<u8>) {
+        self.srgb.write(writer);
+        self.linear.write(writer);
+    }
+
+    fn read(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            srgb: Cached::read(reader)?,
+            linear: Cached::read(reader)?,
+        })
+    }
+}
+
+/// wgpu textures of a model, uploaded from its [TextureImages]
+pub struct Textures {
+    srgb: Vec<Option<Texture>>,
+    linear: Vec<Option<Texture>>,
+}
+
+impl Textures {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, images: &TextureImages) -> Self {
+        let upload = |mip_chains: &[Option<MipChain>]| {
+            (mip_chains.iter())
+                .map(|mip_chain| {
+                    let mip_chain = mip_chain.as_ref()?;
+                    Some(Texture::from_mip_chain(device, queue, mip_chain))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let srgb = upload(&images.srgb);
+        let linear = upload(&images.linear);
+
+        #[cfg(feature = "debug_gltf")]
+        log::info!(
+            "{} sRGB and {} linear textures",
+            srgb.iter().flatten().count(),
+            linear.iter().flatten().count()
+        );
+
+        Self { srgb, linear }
+    }
+
+    /// Texture of the image `image_index` in `color_space`, it must be used by
+    /// a material slot of that color space
+    pub fn get(&self, image_index: usize, color_space: ColorSpace) -> &Texture {
+        let textures = match color_space {
+            ColorSpace::Srgb => &self.srgb,
+            ColorSpace::Linear => &self.linear,
+        };
+
+        textures[image_index]
+            .as_ref()
+            .expect("Image not used in this color space")
+    }
+}
@@ -0,0 +1,508 @@
+use std::{collections::HashMap, fmt};
+
+use gltf::{mesh::Mode, Semantic};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Harmless, usually left over by the exporter
+    Info,
+    /// Loads, but likely renders or animates wrongly
+    Warning,
+    /// Can't be loaded
+    Error,
+}
+
+/// Issue found in a glTF document by [validate]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    MissingNormals {
+        mesh: usize,
+        primitive: usize,
+    },
+    /// Triangles with a zero area
+    DegenerateTriangles {
+        mesh: usize,
+        primitive: usize,
+        count: usize,
+    },
+    /// Vertices whose joint weights don't sum to 1
+    UnnormalizedJointWeights {
+        mesh: usize,
+        primitive: usize,
+        count: usize,
+    },
+    /// Rotation keyframes that aren't unit quaternions
+    NonUnitQuaternions {
+        animation: usize,
+        channel: usize,
+        count: usize,
+    },
+    IndexOutOfRange {
+        mesh: usize,
+        primitive: usize,
+        max_index: u32,
+        vertex_count: usize,
+    },
+    UnusedMaterial {
+        material: usize,
+    },
+    UnusedTexture {
+        texture: usize,
+    },
+    /// Mesh instantiated both by skinned and unskinned nodes
+    MixedSkinning {
+        mesh: usize,
+    },
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        use Diagnostic::*;
+
+        match self {
+            UnusedMaterial { .. } | UnusedTexture { .. } => Severity::Info,
+            MissingNormals { .. }
+            | DegenerateTriangles { .. }
+            | UnnormalizedJointWeights { .. }
+            | NonUnitQuaternions { .. }
+            | MixedSkinning { .. } => Severity::Warning,
+            IndexOutOfRange { .. } => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Diagnostic::*;
+
+        match self {
+            MissingNormals { mesh, primitive } => {
+                write!(f, "mesh {mesh} primitive {primitive}: no normals")
+            }
+            DegenerateTriangles {
+                mesh,
+                primitive,
+                count,
+            } => write!(
+                f,
+                "mesh {mesh} primitive {primitive}: {count} degenerate triangles"
+            ),
+            UnnormalizedJointWeights {
+                mesh,
+                primitive,
+                count,
+            } => write!(
+                f,
+                "mesh {mesh} primitive {primitive}: {count} vertices with joint weights not summing to 1"
+            ),
+            NonUnitQuaternions {
+                animation,
+                channel,
+                count,
+            } => write!(
+                f,
+                "animation {animation} channel {channel}: {count} non unit rotations"
+            ),
+            IndexOutOfRange {
+                mesh,
+                primitive,
+                max_index,
+                vertex_count,
+            } => write!(
+                f,
+                "mesh {mesh} primitive {primitive}: index {max_index} out of {vertex_count} vertices"
+            ),
+            UnusedMaterial { material } => write!(f, "material {material} is never used"),
+            UnusedTexture { texture } => write!(f, "texture {texture} is never used"),
+            MixedSkinning { mesh } => {
+                write!(f, "mesh {mesh} is used by skinned and unskinned nodes")
+            }
+        }
+    }
+}
+
+/// Tolerance of the unit length and weight sum checks, loose enough for
+/// normalized integer data
+const EPSILON: f32 = 1e-2;
+
+/// Lints `document`, from the most to the least severe diagnostic
+pub fn validate(document: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            validate_primitive(&mesh, &primitive, buffers, &mut diagnostics);
+        }
+    }
+
+    for animation in document.animations() {
+        for (channel_index, channel) in animation.channels().enumerate() {
+            let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+            let Some(gltf::animation::util::ReadOutputs::Rotations(rotations)) =
+                reader.read_outputs()
+            else {
+                continue;
+            };
+
+            // Cubic spline tangents don't have to be unit quaternions
+            let cubic_spline =
+                channel.sampler().interpolation() == gltf::animation::Interpolation::CubicSpline;
+            let count = rotations
+                .into_f32()
+                .enumerate()
+                .filter(|(i, _)| !cubic_spline || i % 3 == 1)
+                .filter(|(_, rotation)| {
+                    let length = glam::Vec4::from_array(*rotation).length();
+                    (length - 1.0).abs() > EPSILON
+                })
+                .count();
+
+            if count > 0 {
+                diagnostics.push(Diagnostic::NonUnitQuaternions {
+                    animation: animation.index(),
+                    channel: channel_index,
+                    count,
+                });
+            }
+        }
+    }
+
+    // Skinned or not, for every node instantiating each mesh
+    let mut mesh_skinning = HashMap::<usize, (bool, bool)>::new();
+    for node in document.nodes() {
+        if let Some(mesh) = node.mesh() {
+            let (skinned, unskinned) = mesh_skinning.entry(mesh.index()).or_default();
+            if node.skin().is_some() {
+                *skinned = true;
+            } else {
+                *unskinned = true;
+            }
+        }
+    }
+    let mut mixed_meshes = mesh_skinning
+        .into_iter()
+        .filter(|(_, (skinned, unskinned))| *skinned && *unskinned)
+        .map(|(mesh, _)| mesh)
+        .collect::<Vec<_>>();
+    mixed_meshes.sort_unstable();
+    diagnostics.extend(
+        mixed_meshes
+            .into_iter()
+            .map(|mesh| Diagnostic::MixedSkinning { mesh }),
+    );
+
+    let mut used_materials = vec![false; document.materials().len()];
+    for primitive in document.meshes().flat_map(|mesh| mesh.primitives()) {
+        if let Some(material) = primitive.material().index() {
+            used_materials[material] = true;
+        }
+    }
+
+    let mut used_textures = vec![false; document.textures().len()];
+    for material in document.materials() {
+        let pbr = material.pbr_metallic_roughness();
+
+        let textures = [
+            pbr.base_color_texture().map(|info| info.texture()),
+            pbr.metallic_roughness_texture().map(|info| info.texture()),
+            material.emissive_texture().map(|info| info.texture()),
+            material.normal_texture().map(|info| info.texture()),
+            material.occlusion_texture().map(|info| info.texture()),
+        ];
+        for texture in textures.into_iter().flatten() {
+            used_textures[texture.index()] = true;
+        }
+    }
+
+    diagnostics.extend(
+        (used_materials.into_iter().enumerate())
+            .filter(|(_, used)| !used)
+            .map(|(material, _)| Diagnostic::UnusedMaterial { material }),
+    );
+    diagnostics.extend(
+        (used_textures.into_iter().enumerate())
+            .filter(|(_, used)| !used)
+            .map(|(texture, _)| Diagnostic::UnusedTexture { texture }),
+    );
+
+    diagnostics.sort_by_key(|diagnostic| std::cmp::Reverse(diagnostic.severity()));
+    diagnostics
+}
+
+fn validate_primitive(
+    mesh: &gltf::Mesh,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let (mesh, primitive_index) = (mesh.index(), primitive.index());
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+
+    let Some(positions) = reader.read_positions() else {
+        // Rejected when loading
+        return;
+    };
+    let positions = positions.map(glam::Vec3::from).collect::<Vec<_>>();
+    let vertex_count = positions.len();
+
+    let is_triangles = matches!(
+        primitive.mode(),
+        Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+    );
+    if is_triangles && primitive.get(&Semantic::Normals).is_none() {
+        diagnostics.push(Diagnostic::MissingNormals {
+            mesh,
+            primitive: primitive_index,
+        });
+    }
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+        None => (0..vertex_count as u32).collect(),
+    };
+
+    if let Some(&max_index) = indices.iter().max() {
+        if max_index as usize >= vertex_count {
+            diagnostics.push(Diagnostic::IndexOutOfRange {
+                mesh,
+                primitive: primitive_index,
+                max_index,
+                vertex_count,
+            });
+            // The other checks would read out of bounds
+            return;
+        }
+    }
+
+    if is_triangles {
+        let count = triangles(primitive.mode(), &indices)
+            .filter(|&[a, b, c]| {
+                let [a, b, c] = [a, b, c].map(|index| positions[index as usize]);
+                (b - a).cross(c - a).length_squared() == 0.0
+            })
+            .count();
+
+        if count > 0 {
+            diagnostics.push(Diagnostic::DegenerateTriangles {
+                mesh,
+                primitive: primitive_index,
+                count,
+            });
+        }
+    }
+
+    if let Some(weights) = reader.read_weights(0) {
+        let mut sums = weights
+            .into_f32()
+            .map(|weights| weights.iter().sum::<f32>())
+            .collect::<Vec<_>>();
+        // Skins with more than 4 joints per vertex spread them over sets
+        if let Some(weights) = reader.read_weights(1) {
+            for (sum, weights) in sums.iter_mut().zip(weights.into_f32()) {
+                *sum += weights.iter().sum::<f32>();
+            }
+        }
+
+        let count = sums
+            .into_iter()
+            .filter(|sum| (sum - 1.0).abs() > EPSILON)
+            .count();
+        if count > 0 {
+            diagnostics.push(Diagnostic::UnnormalizedJointWeights {
+                mesh,
+                primitive: primitive_index,
+                count,
+            });
+        }
+    }
+}
+
+/// Vertex indices of every triangle drawn with `mode`
+fn triangles(mode: Mode, indices: &[u32]) -> Box<dyn Iterator<Item = [u32; 3]> + '_> {
+    match mode {
+        Mode::Triangles => Box::new(
+            indices
+                .chunks_exact(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]]),
+        ),
+        Mode::TriangleStrip => Box::new(
+            indices
+                .windows(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]]),
+        ),
+        Mode::TriangleFan => match indices.split_first() {
+            Some((&center, rest)) => {
+                Box::new(rest.windows(2).map(move |edge| [center, edge[0], edge[1]]))
+            }
+            None => Box::new(std::iter::empty()),
+        },
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Document with a single buffer holding `bytes`
+    fn document(
+        json: serde_json::Value,
+        bytes: Vec<u8>,
+    ) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        let gltf = gltf::Gltf::from_slice(json.to_string().as_bytes()).expect("Invalid test glTF");
+        (gltf.document, vec![gltf::buffer::Data(bytes)])
+    }
+
+    /// Triangle from `positions` and u16 `indices`, with the given extra
+    /// primitive attributes and document members
+    fn triangle(
+        positions: [[f32; 3]; 3],
+        indices: [u16; 3],
+        extra: serde_json::Value,
+    ) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        let mut bytes = bytemuck::cast_slice::<_, u8>(&positions).to_vec();
+        bytes.extend_from_slice(bytemuck::cast_slice(&indices));
+        bytes.extend_from_slice(&[0; 2]);
+        let (min, max) = (
+            [0, 1, 2].map(|i| positions.iter().map(|p| p[i]).fold(f32::MAX, f32::min)),
+            [0, 1, 2].map(|i| positions.iter().map(|p| p[i]).fold(f32::MIN, f32::max)),
+        );
+
+        let mut json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": bytes.len() }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": min, "max": max },
+                { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+            ],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1 }] }],
+        });
+        if let (Some(json), Some(extra)) = (json.as_object_mut(), extra.as_object()) {
+            json.extend(extra.clone());
+        }
+
+        document(json, bytes)
+    }
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    #[test]
+    fn valid_triangle_only_misses_normals() {
+        let (document, buffers) = triangle(TRIANGLE, [0, 1, 2], serde_json::json!({}));
+
+        assert_eq!(
+            validate(&document, &buffers),
+            [Diagnostic::MissingNormals {
+                mesh: 0,
+                primitive: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn collinear_triangles_are_degenerate() {
+        let line = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]];
+        let (document, buffers) = triangle(line, [0, 1, 2], serde_json::json!({}));
+
+        assert!(
+            validate(&document, &buffers).contains(&Diagnostic::DegenerateTriangles {
+                mesh: 0,
+                primitive: 0,
+                count: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors_listed_first() {
+        let unused = serde_json::json!({ "materials": [{}] });
+        let (document, buffers) = triangle(TRIANGLE, [0, 1, 5], unused);
+
+        let diagnostics = validate(&document, &buffers);
+        assert_eq!(
+            diagnostics[0],
+            Diagnostic::IndexOutOfRange {
+                mesh: 0,
+                primitive: 0,
+                max_index: 5,
+                vertex_count: 3,
+            }
+        );
+        assert_eq!(diagnostics[0].severity(), Severity::Error);
+        assert_eq!(
+            diagnostics.last(),
+            Some(&Diagnostic::UnusedMaterial { material: 0 })
+        );
+        assert!(diagnostics
+            .windows(2)
+            .all(|pair| pair[0].severity() >= pair[1].severity()));
+    }
+
+    #[test]
+    fn unused_materials_and_textures_are_reported() {
+        let unused = serde_json::json!({
+            "materials": [{}],
+            "images": [{ "uri": "unused.png" }],
+            "textures": [{ "source": 0 }],
+        });
+        let (document, buffers) = triangle(TRIANGLE, [0, 1, 2], unused);
+
+        let diagnostics = validate(&document, &buffers);
+        assert!(diagnostics.contains(&Diagnostic::UnusedMaterial { material: 0 }));
+        assert!(diagnostics.contains(&Diagnostic::UnusedTexture { texture: 0 }));
+    }
+
+    #[test]
+    fn non_unit_rotation_keyframes_are_reported() {
+        let times = [0f32, 1.0];
+        let rotations = [[0f32, 0.0, 0.0, 1.0], [0.0, 0.0, 0.0, 2.0]];
+        let mut bytes = bytemuck::cast_slice::<_, u8>(&times).to_vec();
+        bytes.extend_from_slice(bytemuck::cast_slice(&rotations));
+
+        let json = serde_json::json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": bytes.len() }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 8 },
+                { "buffer": 0, "byteOffset": 8, "byteLength": 32 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0.0], "max": [1.0] },
+                { "bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC4" }
+            ],
+            "nodes": [{}],
+            "animations": [{
+                "samplers": [{ "input": 0, "output": 1 }],
+                "channels": [{ "sampler": 0, "target": { "node": 0, "path": "rotation" } }]
+            }],
+        });
+        let (document, buffers) = document(json, bytes);
+
+        assert_eq!(
+            validate(&document, &buffers),
+            [Diagnostic::NonUnitQuaternions {
+                animation: 0,
+                channel: 0,
+                count: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn strips_and_fans_share_vertices() {
+        let indices = [0, 1, 2, 3, 4];
+
+        let strip = triangles(Mode::TriangleStrip, &indices).collect::<Vec<_>>();
+        assert_eq!(strip, [[0, 1, 2], [1, 2, 3], [2, 3, 4]]);
+
+        let fan = triangles(Mode::TriangleFan, &indices).collect::<Vec<_>>();
+        assert_eq!(fan, [[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+
+        assert_eq!(triangles(Mode::Triangles, &indices).count(), 1);
+        assert_eq!(triangles(Mode::Lines, &indices).count(), 0);
+    }
+}
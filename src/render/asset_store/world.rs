@@ -1,42 +1,146 @@
-use crate::render::asset_store::{material::AlphaMode, Model};
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::render::asset_store::{
+    loader::{LoadState, ModelLoader},
+    AssetCache, Model, ModelAsset,
+};
 
 pub struct AssetRegistry {
     pub opaque_models: Vec<Model>,
     pub transparent_models: Vec<Model>,
+    /// Uploaded assets, shared by the models placed from them
+    assets: Vec<Rc<ModelAsset>>,
+    /// Placements of models whose asset isn't uploaded yet
+    pending_models: Vec<(PathBuf, glam::Mat4)>,
+    loader: ModelLoader,
 }
 
 impl AssetRegistry {
-    pub async fn new<P: AsRef<std::path::Path>>(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        gltf_paths: &[P],
-    ) -> AssetRegistry {
-        let mut opaque_models = Vec::new();
-        let mut transparent_models = Vec::new();
+    /// Starts loading `gltf_paths` in the background, placing a model of
+    /// each at the origin, see [Self::update_loads]. Assets are read from
+    /// and written to `cache` when given
+    pub fn new<P: AsRef<Path>>(gltf_paths: &[P], cache: Option<AssetCache>) -> AssetRegistry {
+        let mut registry = Self {
+            opaque_models: Vec::new(),
+            transparent_models: Vec::new(),
+            assets: Vec::new(),
+            pending_models: Vec::new(),
+            loader: ModelLoader::default(),
+        };
+        registry.loader.set_cache(cache);
 
         for path in gltf_paths {
-            let model = Model::from_path(path, device, queue).await;
-            #[cfg(feature = "debug_gltf")]
-            let model = model.expect(&format!("Failed to load model {}", path.as_ref().display()));
-            #[cfg(not(feature = "debug_gltf"))]
-            let model = model.expect("Failed to load model");
-
-            let transparent = model
-                .packed_primitives
-                .per_primitives
-                .iter()
-                .any(|p| p.material.alpha_mode != AlphaMode::Opaque);
-
-            if transparent {
-                transparent_models.push(model);
+            registry.instantiate(path, glam::Mat4::IDENTITY);
+        }
+
+        registry
+    }
+
+    /// Places a model of the asset at `path`, which is only loaded once
+    /// however many models share it. The model appears when its asset is
+    /// uploaded, and is dropped if it fails to load
+    pub fn instantiate<P: AsRef<Path>>(&mut self, path: P, transform: glam::Mat4) {
+        let path = path.as_ref();
+
+        // Assets that failed to load are tried again
+        let state =
+            (self.loader.states()).find_map(|(loaded, state)| (loaded == path).then_some(state));
+        if matches!(state, None | Some(LoadState::Failed(_))) {
+            self.loader.load(path);
+        }
+        self.pending_models.push((path.to_path_buf(), transform));
+    }
+
+    /// Loads the asset at `path` again in the background, updating the
+    /// models placed from it once ready. The previous asset stays when the
+    /// file is invalid
+    pub fn reload<P: AsRef<Path>>(&mut self, path: P) {
+        self.loader.load(path);
+    }
+
+    /// Uploads the assets loaded since the last call and places their
+    /// pending models, returning the paths of the uploaded assets
+    pub fn update_loads(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<PathBuf> {
+        let mut uploaded = Vec::new();
+
+        for data in self.loader.loaded() {
+            let asset = ModelAsset::new(data, device, queue);
+            self.loader.set_ready(&asset.path);
+            uploaded.push(asset.path.clone());
+            self.insert(Rc::new(asset), device);
+        }
+
+        for (path, transform) in std::mem::take(&mut self.pending_models) {
+            let Some(asset) = self.assets.iter().find(|asset| asset.path == path) else {
+                let failed = (self.loader.states())
+                    .any(|(loaded, state)| loaded == path && matches!(state, LoadState::Failed(_)));
+                if !failed {
+                    self.pending_models.push((path, transform));
+                }
+                continue;
+            };
+
+            let models = if asset.is_transparent() {
+                &mut self.transparent_models
             } else {
-                opaque_models.push(model);
+                &mut self.opaque_models
+            };
+            models.push(Model::new(asset.clone(), transform, device));
+        }
+
+        uploaded
+    }
+
+    /// Progress of every asset loaded, in loading order
+    pub fn load_states(&self) -> impl Iterator<Item = (&Path, &LoadState)> {
+        self.loader.states()
+    }
+
+    /// Paths of the uploaded assets, in upload order
+    pub fn asset_paths(&self) -> impl Iterator<Item = &Path> {
+        self.assets.iter().map(|asset| asset.path.as_path())
+    }
+
+    /// Adds `asset`, the models of the one previously loaded from the same
+    /// path switching to it in place
+    fn insert(&mut self, asset: Rc<ModelAsset>, device: &wgpu::Device) {
+        for model in self.models_mut() {
+            if model.asset.path == asset.path {
+                model.set_asset(asset.clone(), device);
             }
         }
 
-        Self {
-            opaque_models,
-            transparent_models,
+        // The new version may have gained or lost its transparent materials
+        let (from, to) = if asset.is_transparent() {
+            (&mut self.opaque_models, &mut self.transparent_models)
+        } else {
+            (&mut self.transparent_models, &mut self.opaque_models)
+        };
+        let (moved, kept) = (std::mem::take(from).into_iter())
+            .partition::<Vec<_>, _>(|model| model.asset.path == asset.path);
+        *from = kept;
+        to.extend(moved);
+
+        match self
+            .assets
+            .iter()
+            .position(|loaded| loaded.path == asset.path)
+        {
+            Some(index) => self.assets[index] = asset,
+            None => self.assets.push(asset),
         }
     }
+
+    pub fn models(&self) -> impl Iterator<Item = &Model> {
+        self.opaque_models.iter().chain(&self.transparent_models)
+    }
+
+    pub fn models_mut(&mut self) -> impl Iterator<Item = &mut Model> {
+        self.opaque_models
+            .iter_mut()
+            .chain(&mut self.transparent_models)
+    }
 }
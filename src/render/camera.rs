@@ -15,6 +15,40 @@ impl Angle {
     }
 }
 
+/// Projection of a camera authored in a glTF file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective {
+        yfov: f32,
+        znear: f32,
+        /// Infinite projection when unset
+        zfar: Option<f32>,
+    },
+    /// The horizontal magnification follows from the window aspect ratio
+    Orthographic { ymag: f32, znear: f32, zfar: f32 },
+}
+
+impl From<gltf::camera::Projection<'_>> for Projection {
+    fn from(projection: gltf::camera::Projection) -> Self {
+        use gltf::camera::Projection::{Orthographic, Perspective};
+
+        // The aspect ratio of the glTF camera is ignored, the view always
+        // fills the window
+        match projection {
+            Perspective(perspective) => Projection::Perspective {
+                yfov: perspective.yfov(),
+                znear: perspective.znear(),
+                zfar: perspective.zfar(),
+            },
+            Orthographic(orthographic) => Projection::Orthographic {
+                ymag: orthographic.ymag(),
+                znear: orthographic.znear(),
+                zfar: orthographic.zfar(),
+            },
+        }
+    }
+}
+
 pub struct Camera {
     eye: glam::Vec3,
     // Horizontal angle
@@ -27,6 +61,10 @@ pub struct Camera {
     znear: f32,
     zfar: f32,
 
+    /// glTF camera looked through instead of the free-fly one, with the
+    /// world transform of its node
+    node_view: Option<(Projection, glam::Mat4)>,
+
     buffer: wgpu::Buffer,
     camera_bind_group_layout: wgpu::BindGroupLayout,
     camera_bind_group: wgpu::BindGroup,
@@ -93,6 +131,8 @@ impl Camera {
             znear,
             zfar,
 
+            node_view: None,
+
             buffer,
             camera_bind_group_layout,
             camera_bind_group,
@@ -100,6 +140,10 @@ impl Camera {
     }
 
     fn projection_matrix(&self) -> glam::Mat4 {
+        if let Some((projection, transform)) = self.node_view {
+            return self.node_projection_matrix(projection, transform);
+        }
+
         let (sin_pitch, cos_pitch) = self.pitch.to_radians().sin_cos();
         let (sin_yaw, cos_yaw) = self.yaw.to_radians().sin_cos();
 
@@ -111,6 +155,44 @@ impl Camera {
         projection * view
     }
 
+    /// glTF cameras look down their node -Z axis, with +Y up
+    fn node_projection_matrix(&self, projection: Projection, transform: glam::Mat4) -> glam::Mat4 {
+        let eye = transform.w_axis.truncate();
+        let forward = -transform.z_axis.truncate();
+        let up = transform.y_axis.truncate();
+
+        let view = glam::Mat4::look_to_lh(eye, forward.normalize(), up.normalize());
+        let projection = match projection {
+            Projection::Perspective {
+                yfov,
+                znear,
+                zfar: Some(zfar),
+            } => glam::Mat4::perspective_lh(yfov, self.aspect, znear, zfar),
+            Projection::Perspective {
+                yfov,
+                znear,
+                zfar: None,
+            } => glam::Mat4::perspective_infinite_lh(yfov, self.aspect, znear),
+            Projection::Orthographic { ymag, znear, zfar } => {
+                let xmag = ymag * self.aspect;
+                glam::Mat4::orthographic_lh(-xmag, xmag, -ymag, ymag, znear, zfar)
+            }
+        };
+
+        projection * view
+    }
+
+    /// Looks through a glTF camera, `transform` being the world transform of
+    /// its node
+    pub fn set_node_view(&mut self, projection: Projection, transform: glam::Mat4) {
+        self.node_view = Some((projection, transform));
+    }
+
+    /// Goes back to the free-fly camera, where it was left
+    pub fn clear_node_view(&mut self) {
+        self.node_view = None;
+    }
+
     pub fn update_projection_matrix(&self, queue: &wgpu::Queue) {
         let projection_matrix = self.projection_matrix();
 
@@ -130,6 +212,10 @@ impl Camera {
         self.eye = position.into();
     }
 
+    pub fn position(&self) -> glam::Vec3 {
+        self.eye
+    }
+
     pub fn move_yaw_pitch(&mut self, yaw: f32, pitch: f32) {
         const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - f32::EPSILON;
 
@@ -0,0 +1,70 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::utils::Instant;
+
+/// Minimum time between two checks of the watched files
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Asset to rebuild when its file changes
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchedAsset {
+    Model(PathBuf),
+    Shader(&'static str),
+}
+
+/// Notices changes of files on disk by polling their modification time
+pub struct FileWatcher {
+    files: HashMap<PathBuf, (Option<SystemTime>, WatchedAsset)>,
+    last_poll: Instant,
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        Self {
+            files: HashMap::new(),
+            last_poll: Instant::now(),
+        }
+    }
+}
+
+impl FileWatcher {
+    /// Files already watched keep their asset and their last known
+    /// modification time, so that no change is missed
+    pub fn watch<P: AsRef<Path>>(&mut self, path: P, asset: WatchedAsset) {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified_time(&path);
+
+        self.files.entry(path).or_insert((modified, asset));
+    }
+
+    /// Assets whose file was written since the last poll
+    pub fn poll(&mut self) -> Vec<WatchedAsset> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for (path, (modified, asset)) in &mut self.files {
+            let current = modified_time(path);
+            // Files missing for a moment while being saved aren't reloaded,
+            // and models whose files are written together reload once
+            if current.is_some() && current != *modified && !changed.contains(asset) {
+                changed.push(asset.clone());
+            }
+            *modified = current;
+        }
+
+        changed
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
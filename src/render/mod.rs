@@ -1,21 +1,24 @@
-use crate::utils::Instant;
+use crate::{utils::Instant, Config};
 
 use wgpu::{Adapter, Instance, Surface, TextureFormat};
 use winit::{dpi::PhysicalSize, window::Window};
 
 pub use crate::render::texture::Texture;
+pub use asset_store::{Extras, Model, Node, NodeIndex};
 
 use self::render_pipeline::TexturePipeline;
 
 mod asset_store;
 mod camera;
+#[cfg(not(target_arch = "wasm32"))]
+mod hot_reload;
 pub(crate) mod render_pipeline;
 mod shaders;
 mod texture;
 pub mod utils;
 
 pub struct DrawingContext {
-    time_start: Instant,
+    last_frame_time: Instant,
 
     surface: wgpu::Surface,
     device: wgpu::Device,
@@ -26,10 +29,22 @@ pub struct DrawingContext {
     depth_texture: Texture,
 
     camera: camera::Camera,
+    /// glTF camera looked through, indexed across every model cameras
+    active_camera: Option<usize>,
     pub input_manager: input_manager::InputManager,
 
     asset_registry: asset_store::AssetRegistry,
+    /// Scene shown by the loaded models, see [Config::scene]
+    scene: Option<usize>,
+    /// Clip played by the loaded models, see [Config::animation]
+    animation: Option<String>,
+    /// Assets whose models got the scene and clip options, which hot
+    /// reloads keep
+    configured_assets: std::collections::HashSet<std::path::PathBuf>,
     texture_pipeline: TexturePipeline,
+    /// Models and shaders to rebuild when their file changes
+    #[cfg(not(target_arch = "wasm32"))]
+    file_watcher: hot_reload::FileWatcher,
 
     fill_color: wgpu::Color,
     /// Window has a dimension of 0
@@ -63,8 +78,32 @@ async fn get_adaptater(instance: &Instance, surface: &Surface) -> Adapter {
         .expect("Failed to find an appropriate fallback adapter")
 }
 
+/// `requested` mode if the surface supports it, otherwise its first one
+fn present_mode(
+    requested: Option<wgpu::PresentMode>,
+    surface_capabilities: &wgpu::SurfaceCapabilities,
+) -> wgpu::PresentMode {
+    use wgpu::PresentMode::{AutoNoVsync, AutoVsync};
+
+    let supported = &surface_capabilities.present_modes;
+    match requested {
+        // wgpu falls back to a supported mode for these
+        Some(mode @ (AutoVsync | AutoNoVsync)) => mode,
+        Some(mode) if supported.contains(&mode) => mode,
+        Some(mode) => {
+            log::warn!(
+                "Present mode {:?} unsupported, using {:?}",
+                mode,
+                supported[0]
+            );
+            supported[0]
+        }
+        None => supported[0],
+    }
+}
+
 impl DrawingContext {
-    pub async fn new(window: Window) -> DrawingContext {
+    pub async fn new(window: Window, app_config: Config) -> DrawingContext {
         let device_descriptor = wgpu::DeviceDescriptor {
             features: wgpu::Features::empty(),
             #[cfg(not(feature = "webgl"))]
@@ -113,7 +152,7 @@ impl DrawingContext {
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_capabilities.present_modes[0],
+            present_mode: present_mode(app_config.present_mode, &surface_capabilities),
             alpha_mode: surface_capabilities.alpha_modes[0],
             view_formats: vec![surface_format.add_srgb_suffix()],
         };
@@ -121,7 +160,11 @@ impl DrawingContext {
         surface.configure(&device, &config);
 
         let mut camera = camera::Camera::new(&window, &device);
-        camera.set_camera((-3f32, 0.5f32, -3f32));
+        camera.set_camera(
+            app_config
+                .camera_position
+                .unwrap_or(glam::Vec3::new(-3f32, 0.5f32, -3f32)),
+        );
         camera.update_projection_matrix(&queue);
 
         let texture_pipeline = TexturePipeline::new(
@@ -129,22 +172,35 @@ impl DrawingContext {
             &config,
             camera.bind_group_layout(),
             Texture::color_texture_bind_group_layout(&device),
+            &asset_store::joint_matrices_bind_group_layout(&device),
         );
 
         let depth_texture = Texture::create_depth_texture(&device, &config);
 
-        const ASSETS: &[&str] = &["assets/CesiumMilkTruck.glb"];
-        let asset_registry = asset_store::AssetRegistry::new(&device, &queue, ASSETS).await;
+        // Browsers have no file system to cache into
+        #[cfg(not(target_arch = "wasm32"))]
+        let asset_cache = app_config.cache_dir.map(asset_store::AssetCache::new);
+        #[cfg(target_arch = "wasm32")]
+        let asset_cache = None;
+        let asset_registry = asset_store::AssetRegistry::new(&app_config.models, asset_cache);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let file_watcher = {
+            use hot_reload::{FileWatcher, WatchedAsset};
+
+            let mut file_watcher = FileWatcher::default();
+            for path in &app_config.models {
+                file_watcher.watch(path, WatchedAsset::Model(path.clone()));
+            }
+            for name in shaders::SHADERS {
+                file_watcher.watch(shaders::shader_path(name), WatchedAsset::Shader(name));
+            }
 
-        let fill_color = wgpu::Color {
-            r: 9f64 / 255f64,
-            g: 46f64 / 255f64,
-            b: 32f64 / 255f64,
-            a: 1.0,
+            file_watcher
         };
 
         Self {
-            time_start: Instant::now(),
+            last_frame_time: Instant::now(),
 
             config,
             device,
@@ -155,12 +211,18 @@ impl DrawingContext {
             depth_texture,
 
             camera,
+            active_camera: None,
             input_manager: input_manager::InputManager::new(),
 
             asset_registry,
+            scene: app_config.scene,
+            animation: app_config.animation,
+            configured_assets: std::collections::HashSet::new(),
             texture_pipeline,
+            #[cfg(not(target_arch = "wasm32"))]
+            file_watcher,
 
-            fill_color,
+            fill_color: app_config.fill_color,
             minimized: false,
         }
     }
@@ -245,6 +307,245 @@ impl DrawingContext {
             .move_yaw_pitch(mouse_delta.0 as f32, mouse_delta.1 as f32);
 
         self.camera.update_projection_matrix(&self.queue);
+
+        if self.input_manager.consume_next_scene() {
+            self.next_scene();
+        }
+        if self.input_manager.consume_next_camera() {
+            self.next_camera();
+        }
+        if self.input_manager.consume_print_scene_tree() {
+            self.print_scene_tree();
+        }
+        if self.input_manager.consume_place_models() {
+            self.place_models();
+        }
+        self.process_animation_inputs();
+    }
+
+    fn next_scene(&mut self) {
+        for model in self.asset_registry.models_mut() {
+            let scene_count = model.scene_names().count();
+            if scene_count == 0 {
+                continue;
+            }
+
+            let scene = (model.active_scene() + 1) % scene_count;
+            model.set_scene(scene);
+
+            #[cfg(feature = "debug_gltf")]
+            log::info!(
+                "Showing scene #{} ({})",
+                scene,
+                model.scene_names().nth(scene).flatten().unwrap_or("None")
+            );
+        }
+    }
+
+    /// Cycles through the cameras of every model, then the free-fly camera
+    fn next_camera(&mut self) {
+        let camera_count = self
+            .asset_registry
+            .models()
+            .map(|model| model.scene_cameras().len())
+            .sum::<usize>();
+
+        self.active_camera = match self.active_camera {
+            None if camera_count > 0 => Some(0),
+            Some(camera) if camera + 1 < camera_count => Some(camera + 1),
+            _ => None,
+        };
+
+        #[cfg(feature = "debug_gltf")]
+        match self.active_camera {
+            Some(camera) => log::info!("Looking through camera #{}", camera),
+            None => log::info!("Looking through the free-fly camera"),
+        }
+
+        if self.active_camera.is_none() {
+            self.camera.clear_node_view();
+            self.camera.update_projection_matrix(&self.queue);
+        }
+    }
+
+    /// Logs the node hierarchy of the active scene of every model, with the
+    /// transforms of the current animation time
+    fn print_scene_tree(&self) {
+        fn print_node(model: &asset_store::Model, node: asset_store::Node) {
+            let depth = std::iter::successors(node.parent(), asset_store::Node::parent).count();
+            let indent = "|    ".repeat(depth);
+            let name = node.name().unwrap_or("None");
+
+            let local = node.local_transform();
+            let (scale, rotation, translation) =
+                node.world_transform().to_scale_rotation_translation();
+            log::info!(
+                r#"{indent}Node#{}: "{name}" mesh: {:?} materials: {:?}"#,
+                node.index().0,
+                node.mesh_name(),
+                node.material_names().collect::<Vec<_>>(),
+            );
+            log::info!(
+                "{indent}  local: translation {:.2?} rotation {:.2?} scale {:.2?}",
+                local.translation,
+                local.rotation,
+                local.scale,
+            );
+            if !node.extras().is_null() {
+                log::info!("{indent}  extras: {}", node.extras());
+            }
+            log::info!(
+                "{indent}  world: translation {:.2?} rotation {:.2?} scale {:.2?}",
+                translation,
+                rotation,
+                scale,
+            );
+
+            // Lookups by name only find the first node
+            let named = node.name().and_then(|name| model.node_by_name(name));
+            if let Some(named) = named.filter(|named| named.index() != node.index()) {
+                log::warn!("{indent}  name shadowed by Node#{}", named.index().0);
+            }
+
+            for child in node.children() {
+                print_node(model, child);
+            }
+        }
+
+        for model in self.asset_registry.models() {
+            if !model.scene_extras().is_null() {
+                log::info!("Scene extras: {}", model.scene_extras());
+            }
+            for root in model.scene_roots() {
+                print_node(model, root);
+            }
+        }
+    }
+
+    /// Places another model of every loaded asset on the ground below the
+    /// camera, sharing the buffers and textures of the others
+    fn place_models(&mut self) {
+        let position = self.camera.position() * glam::Vec3::new(1f32, 0f32, 1f32);
+        let transform = glam::Mat4::from_translation(position);
+
+        let paths = (self.asset_registry.asset_paths())
+            .map(std::path::Path::to_path_buf)
+            .collect::<Vec<_>>();
+        for path in paths {
+            #[cfg(feature = "debug_gltf")]
+            log::info!("Placing {} at {:.2?}", path.display(), position);
+
+            self.asset_registry.instantiate(path, transform);
+        }
+    }
+
+    /// Follows the node of the active glTF camera, which may be animated
+    fn update_node_camera(&mut self) {
+        let Some(active_camera) = self.active_camera else {
+            return;
+        };
+
+        let node_camera = self
+            .asset_registry
+            .models()
+            .flat_map(|model| {
+                let cameras = model.scene_cameras().into_iter();
+                cameras.filter_map(|(node, projection)| {
+                    Some((projection, model.node(node)?.world_transform()))
+                })
+            })
+            .nth(active_camera);
+
+        match node_camera {
+            Some((projection, transform)) => self.camera.set_node_view(projection, transform),
+            // Switching scenes can remove cameras
+            None => {
+                self.active_camera = None;
+                self.camera.clear_node_view();
+            }
+        }
+        self.camera.update_projection_matrix(&self.queue);
+    }
+
+    fn process_animation_inputs(&mut self) {
+        use asset_store::{LayerBlend, PlaybackMode, PlaybackState};
+
+        /// Duration of a step, animations are usually authored at 30 fps
+        const ANIMATION_STEP: f32 = 1f32 / 30f32;
+        const CROSS_FADE_DURATION: f32 = 0.3f32;
+        const BLEND_LAYER_WEIGHT: f32 = 0.5f32;
+
+        let toggle = self.input_manager.consume_toggle_animation();
+        let stop = self.input_manager.consume_stop_animation();
+        let next = self.input_manager.consume_next_animation();
+        let cycle_mode = self.input_manager.consume_cycle_playback_mode();
+        let speed_factor = self.input_manager.consume_animation_speed_factor();
+        let step = self.input_manager.consume_animation_step();
+        let blend_layer = self.input_manager.consume_toggle_blend_layer();
+        let additive_layer = self.input_manager.consume_toggle_additive_layer();
+
+        for model in self.asset_registry.models_mut() {
+            #[cfg(feature = "debug_gltf")]
+            let clip_names = model
+                .animation_names()
+                .map(|name| name.unwrap_or("None").to_owned())
+                .collect::<Vec<_>>();
+            let clip_count = model.animation_names().count();
+            let player = model.animation_player_mut();
+
+            if toggle {
+                match player.state() {
+                    PlaybackState::Playing => player.pause(),
+                    PlaybackState::Paused | PlaybackState::Stopped => player.play(),
+                }
+            }
+
+            if stop {
+                player.stop();
+            }
+
+            if next && clip_count > 0 {
+                let clip = player
+                    .active_clip()
+                    .map_or(0, |clip| (clip + 1) % clip_count);
+                player.cross_fade(Some(clip), CROSS_FADE_DURATION);
+
+                #[cfg(feature = "debug_gltf")]
+                log::info!("Playing animation #{} ({})", clip, clip_names[clip]);
+            }
+
+            // Layers play the clip following the active one on top of it
+            if (blend_layer || additive_layer) && !player.layers().is_empty() {
+                player.clear_layers();
+            } else if (blend_layer || additive_layer) && clip_count > 0 {
+                let clip = player
+                    .active_clip()
+                    .map_or(0, |clip| (clip + 1) % clip_count);
+
+                if blend_layer {
+                    player.add_layer(clip, LayerBlend::Override, BLEND_LAYER_WEIGHT);
+                } else {
+                    player.add_layer(clip, LayerBlend::Additive, 1f32);
+                }
+            }
+
+            if speed_factor != 1f32 {
+                player.set_speed(player.speed() * speed_factor);
+            }
+
+            if step != 0f32 {
+                player.pause();
+                player.seek(player.time() + step * ANIMATION_STEP);
+            }
+
+            if cycle_mode {
+                player.set_mode(match player.mode() {
+                    PlaybackMode::Loop => PlaybackMode::Once,
+                    PlaybackMode::Once => PlaybackMode::PingPong,
+                    PlaybackMode::PingPong => PlaybackMode::Loop,
+                });
+            }
+        }
     }
 
     pub fn set_cursor_middle(&mut self) -> Result<(), winit::error::ExternalError> {
@@ -280,6 +581,18 @@ impl DrawingContext {
                 label: Some("Clear color"),
             });
 
+        #[cfg(not(target_arch = "wasm32"))]
+        self.hot_reload();
+        self.update_loads();
+
+        let delta_time = self.last_frame_time.elapsed().as_secs_f32();
+        self.last_frame_time = Instant::now();
+
+        for opaque in &mut self.asset_registry.opaque_models {
+            opaque.update(&self.device, &self.queue, delta_time);
+        }
+        self.update_node_camera();
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Texture Render Pass"),
@@ -301,20 +614,25 @@ impl DrawingContext {
                 }),
             });
 
-            render_pass.set_pipeline(&self.texture_pipeline);
             render_pass.set_bind_group(0, self.camera.bind_group(), &[]);
+            let mut topology = None;
+
+            for opaque in &self.asset_registry.opaque_models {
+                render_pass.set_bind_group(2, opaque.joint_matrices_bind_group(), &[]);
 
-            for opaque in &mut self.asset_registry.opaque_models {
-                let meshes = opaque.iter(&self.device, &self.time_start);
-                for mesh in meshes {
-                    let texture = mesh.color_texture.as_ref();
-                    let transform = &mesh.instance_transforms_buffer;
-                    let vertices = &mesh.vertex_buffer;
-                    let indices = &mesh.index_buffer;
+                for mesh in opaque.iter() {
+                    let texture = mesh.color_texture;
+                    let transform = mesh.instance_transforms_buffer;
+                    let indices = mesh.indices;
                     let vertex_count = mesh.vertex_count;
 
+                    if topology != Some(mesh.topology) {
+                        topology = Some(mesh.topology);
+                        render_pass.set_pipeline(self.texture_pipeline.get(mesh.topology));
+                    }
+
                     // Vertices
-                    render_pass.set_vertex_buffer(0, vertices.slice(..));
+                    render_pass.set_vertex_buffer(0, mesh.vertices);
                     // Transforms for each instance
                     render_pass.set_vertex_buffer(1, transform.slice(..));
 
@@ -322,12 +640,32 @@ impl DrawingContext {
                         render_pass.set_bind_group(1, texture, &[]);
                     }
 
-                    if let Some(index_buffer) = indices {
-                        render_pass
-                            .set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                        render_pass.draw_indexed(0..vertex_count, 0, 0..mesh.instance_count);
-                    } else {
-                        render_pass.draw(0..vertex_count, 0..mesh.instance_count);
+                    if let Some(indices) = indices {
+                        render_pass.set_index_buffer(indices, wgpu::IndexFormat::Uint32);
+                    }
+
+                    let Some(morphed_vertex_count) = mesh.morphed_vertex_count else {
+                        if indices.is_some() {
+                            render_pass.draw_indexed(0..vertex_count, 0, 0..mesh.instance_count);
+                        } else {
+                            render_pass.draw(0..vertex_count, 0..mesh.instance_count);
+                        }
+                        continue;
+                    };
+
+                    // Each instance draws its own morphed vertices
+                    for instance in 0..mesh.instance_count {
+                        let base_vertex = instance * morphed_vertex_count;
+                        let instances = instance..instance + 1;
+
+                        if indices.is_some() {
+                            let base_vertex =
+                                i32::try_from(base_vertex).expect("Base vertex overflow");
+                            render_pass.draw_indexed(0..vertex_count, base_vertex, instances);
+                        } else {
+                            let vertices = base_vertex..base_vertex + vertex_count;
+                            render_pass.draw(vertices, instances);
+                        }
                     }
                 }
             }
@@ -340,6 +678,124 @@ impl DrawingContext {
         Ok(())
     }
 
+    /// Uploads the models loaded in the background since the last frame
+    fn update_loads(&mut self) {
+        use asset_store::LoadState;
+
+        let uploaded = self.asset_registry.update_loads(&self.device, &self.queue);
+        if uploaded.is_empty() {
+            return;
+        }
+        self.apply_model_options(&uploaded);
+
+        // Reloads may reference other files than the previous version
+        #[cfg(not(target_arch = "wasm32"))]
+        for path in &uploaded {
+            for file in asset_store::external_files(path) {
+                let asset = hot_reload::WatchedAsset::Model(path.clone());
+                self.file_watcher.watch(file, asset);
+            }
+        }
+
+        let states = self.asset_registry.load_states().collect::<Vec<_>>();
+        let done = states
+            .iter()
+            .filter(|(_, state)| matches!(state, LoadState::Ready | LoadState::Failed(_)))
+            .count();
+        log::info!("Loaded {}/{} assets", done, states.len());
+        for (path, state) in states {
+            log::info!("  {}: {}", path.display(), state);
+        }
+    }
+
+    /// Shows the configured scene and plays the configured clip on the
+    /// models of the assets uploaded for the first time, those without them
+    /// are left as is
+    fn apply_model_options(&mut self, uploaded: &[std::path::PathBuf]) {
+        for path in uploaded {
+            if !self.configured_assets.insert(path.clone()) {
+                continue;
+            }
+
+            let mut models = (self.asset_registry.models_mut())
+                .filter(|model| model.asset_path() == path)
+                .peekable();
+            let Some(model) = models.peek() else {
+                continue;
+            };
+
+            let scene = self.scene.filter(|&scene| {
+                let found = scene < model.scene_names().count();
+                if !found {
+                    log::warn!("{}: no scene #{}", path.display(), scene);
+                }
+                found
+            });
+
+            let mut missing_clip = false;
+            for model in models {
+                if let Some(scene) = scene {
+                    model.set_scene(scene);
+                }
+                if let Some(animation) = &self.animation {
+                    missing_clip |= !model.set_active_clip_by_name(animation);
+                }
+            }
+
+            if missing_clip {
+                log::warn!(
+                    "{}: no animation named {:?}",
+                    path.display(),
+                    self.animation
+                );
+            }
+        }
+    }
+
+    /// Rebuilds the models and pipelines whose files changed
+    #[cfg(not(target_arch = "wasm32"))]
+    fn hot_reload(&mut self) {
+        use hot_reload::WatchedAsset;
+
+        for asset in self.file_watcher.poll() {
+            log::info!("Reloading {:?}", asset);
+
+            match asset {
+                WatchedAsset::Model(path) => self.asset_registry.reload(path),
+                WatchedAsset::Shader(name) => self.reload_shader(name),
+            }
+        }
+    }
+
+    /// Keeps the previous shader and pipeline if the new ones fail to build
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_shader(&mut self, name: &str) {
+        let previous_shader = match shaders::rebuild_shader(&self.device, name) {
+            Ok(previous_shader) => previous_shader,
+            Err(error) => {
+                log::error!("Failed to compile shader {}: {}", name, error);
+                return;
+            }
+        };
+
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let texture_pipeline = TexturePipeline::new(
+            &self.device,
+            &self.config,
+            self.camera.bind_group_layout(),
+            Texture::color_texture_bind_group_layout(&self.device),
+            &asset_store::joint_matrices_bind_group_layout(&self.device),
+        );
+
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(error) => {
+                log::error!("Failed to create pipeline from shader {}: {}", name, error);
+                shaders::restore_shader(name, previous_shader);
+            }
+            None => self.texture_pipeline = texture_pipeline,
+        }
+    }
+
     pub fn maintain(&self) {
         self.queue.submit(std::iter::empty());
         self.device.poll(wgpu::Maintain::Poll);
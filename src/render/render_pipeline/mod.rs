@@ -1,4 +1,4 @@
-pub const PRIMITIVE_STATE: wgpu::PrimitiveState = wgpu::PrimitiveState {
+const PRIMITIVE_STATE: wgpu::PrimitiveState = wgpu::PrimitiveState {
     topology: wgpu::PrimitiveTopology::TriangleList,
     strip_index_format: None,
     front_face: wgpu::FrontFace::Cw,
@@ -11,6 +11,18 @@ pub const PRIMITIVE_STATE: wgpu::PrimitiveState = wgpu::PrimitiveState {
     conservative: false,
 };
 
+/// [PRIMITIVE_STATE] drawing `topology`
+pub fn primitive_state(topology: wgpu::PrimitiveTopology) -> wgpu::PrimitiveState {
+    // Index buffers are always 32 bits
+    let strip_index_format = topology.is_strip().then_some(wgpu::IndexFormat::Uint32);
+
+    wgpu::PrimitiveState {
+        topology,
+        strip_index_format,
+        ..PRIMITIVE_STATE
+    }
+}
+
 mod texture;
 
 trait RenderPipeline {}
@@ -1,21 +1,16 @@
-use wgpu::{Device, SurfaceConfiguration};
+use std::collections::HashMap;
 
-use crate::render::asset_store::{PerPrimitive, PrimitiveVertex};
+use wgpu::{Device, PrimitiveTopology, SurfaceConfiguration};
+
+use crate::render::asset_store::{InstanceData, PrimitiveVertex};
 use crate::render::shaders::get_shader;
 use crate::render::texture::Texture;
 
-use crate::render::render_pipeline::PRIMITIVE_STATE;
+use crate::render::render_pipeline::primitive_state;
 
+/// Main pipeline, declined for every primitive topology
 pub struct TexturePipeline {
-    pub pipeline: wgpu::RenderPipeline,
-}
-
-impl std::ops::Deref for TexturePipeline {
-    type Target = wgpu::RenderPipeline;
-
-    fn deref(&self) -> &Self::Target {
-        &self.pipeline
-    }
+    pipelines: HashMap<PrimitiveTopology, wgpu::RenderPipeline>,
 }
 
 impl TexturePipeline {
@@ -24,6 +19,7 @@ impl TexturePipeline {
         config: &SurfaceConfiguration,
         camera_bind_group_layout: &wgpu::BindGroupLayout,
         texture_bind_group_layout: &wgpu::BindGroupLayout,
+        joint_matrices_bind_group_layout: &wgpu::BindGroupLayout,
     ) -> Self {
         #[cfg(feature = "debug_gpu")]
         log::info!("Creating texture pipeline");
@@ -33,14 +29,18 @@ impl TexturePipeline {
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Main Render Pipeline Layout"),
-                bind_group_layouts: &[camera_bind_group_layout, texture_bind_group_layout],
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    texture_bind_group_layout,
+                    joint_matrices_bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
 
         let vertex_state = wgpu::VertexState {
             module: &main_shader,
             entry_point: "vs_main",
-            buffers: &[PrimitiveVertex::desc(), PerPrimitive::transform_desc()],
+            buffers: &[PrimitiveVertex::desc(), InstanceData::desc()],
         };
 
         let fragment_state = wgpu::FragmentState {
@@ -53,28 +53,47 @@ impl TexturePipeline {
             })],
         };
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Main Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: vertex_state,
-            fragment: Some(fragment_state),
-            primitive: PRIMITIVE_STATE,
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: u64::MAX,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        const TOPOLOGIES: [PrimitiveTopology; 5] = [
+            PrimitiveTopology::PointList,
+            PrimitiveTopology::LineList,
+            PrimitiveTopology::LineStrip,
+            PrimitiveTopology::TriangleList,
+            PrimitiveTopology::TriangleStrip,
+        ];
+
+        let create_pipeline = |topology| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Main Render Pipeline"),
+                layout: Some(&render_pipeline_layout),
+                vertex: vertex_state.clone(),
+                fragment: Some(fragment_state.clone()),
+                primitive: primitive_state(topology),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: u64::MAX,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+
+        let pipelines = TOPOLOGIES
+            .into_iter()
+            .map(|topology| (topology, create_pipeline(topology)))
+            .collect();
+
+        TexturePipeline { pipelines }
+    }
 
-        TexturePipeline { pipeline }
+    pub fn get(&self, topology: PrimitiveTopology) -> &wgpu::RenderPipeline {
+        &self.pipelines[&topology]
     }
 }
 
@@ -1,12 +1,20 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
 
 use crate::utils::load_file_string;
 
 pub mod kind;
 
-static mut GLOBAL_SHADERS: Option<HashMap<String, Rc<wgpu::ShaderModule>>> = None;
+static GLOBAL_SHADERS: Mutex<Option<HashMap<String, Arc<wgpu::ShaderModule>>>> = Mutex::new(None);
 
-const SHADERS: [&str; 1] = ["main_shader"];
+pub const SHADERS: [&str; 1] = ["main_shader"];
+
+/// WGSL source of the shader `name`
+pub fn shader_path(name: &str) -> String {
+    format!("assets/{name}.wgsl")
+}
 
 pub async fn build_shaders(device: &wgpu::Device) {
     for shader_name in &SHADERS {
@@ -14,22 +22,26 @@ pub async fn build_shaders(device: &wgpu::Device) {
     }
 }
 
-pub fn get_shader(name: &str) -> Rc<wgpu::ShaderModule> {
+fn global_shaders() -> MutexGuard<'static, Option<HashMap<String, Arc<wgpu::ShaderModule>>>> {
+    GLOBAL_SHADERS.lock().expect("Poisoned shader storage")
+}
+
+pub fn get_shader(name: &str) -> Arc<wgpu::ShaderModule> {
     #[cfg(feature = "debug_shader")]
     log::info!("Getting shader {:?}", name);
 
-    unsafe { GLOBAL_SHADERS.as_ref() }.expect("No shaders built")[name].clone()
+    global_shaders().as_ref().expect("No shaders built")[name].clone()
 }
 
 async fn build_shader(device: &wgpu::Device, name: &str) {
-    if unsafe { GLOBAL_SHADERS.as_ref() }.is_none() {
+    if global_shaders().is_none() {
         #[cfg(feature = "debug_shader")]
         log::info!("Init shader storage");
-        unsafe { GLOBAL_SHADERS = Some(HashMap::new()) };
+        *global_shaders() = Some(HashMap::new());
     }
 
-    let global_shaders = unsafe { GLOBAL_SHADERS.as_mut() };
-    if global_shaders.as_ref().unwrap().contains_key(name) {
+    // Not held across the file read, which may yield
+    if global_shaders().as_ref().unwrap().contains_key(name) {
         #[cfg(feature = "debug_shader")]
         log::info!("Shader already built: {}", name);
         return;
@@ -38,7 +50,7 @@ async fn build_shader(device: &wgpu::Device, name: &str) {
     #[cfg(feature = "debug_shader")]
     log::info!("Building shader {:?}", name);
 
-    let shader: String = load_file_string(format!("assets/{name}.wgsl"))
+    let shader: String = load_file_string(shader_path(name))
         .await
         .expect("Could not read shader");
 
@@ -48,9 +60,43 @@ async fn build_shader(device: &wgpu::Device, name: &str) {
     };
 
     let shader = device.create_shader_module(shader);
-    let shader = Rc::new(shader);
+    let shader = Arc::new(shader);
+
+    (global_shaders().as_mut().unwrap()).insert(String::from(name), shader);
+}
+
+/// Compiles the shader `name` from its source again, returning the module it
+/// replaces. A shader that fails to compile leaves the previous one in place
+#[cfg(not(target_arch = "wasm32"))]
+pub fn rebuild_shader(
+    device: &wgpu::Device,
+    name: &str,
+) -> Result<Arc<wgpu::ShaderModule>, String> {
+    #[cfg(feature = "debug_shader")]
+    log::info!("Rebuilding shader {:?}", name);
+
+    let shader = pollster::block_on(load_file_string(shader_path(name)))
+        .map_err(|error| error.to_string())?;
+
+    let shader = wgpu::ShaderModuleDescriptor {
+        label: Some(name),
+        source: wgpu::ShaderSource::Wgsl(shader.into()),
+    };
+
+    // Catch the naga errors, which panic by default
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = device.create_shader_module(shader);
+    if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+        return Err(error.to_string());
+    }
+
+    Ok(restore_shader(name, Arc::new(shader)))
+}
 
-    global_shaders
-        .unwrap()
-        .insert(String::from(name), shader.clone());
+/// Puts back `shader` as `name`, returning the module it replaces
+#[cfg(not(target_arch = "wasm32"))]
+pub fn restore_shader(name: &str, shader: Arc<wgpu::ShaderModule>) -> Arc<wgpu::ShaderModule> {
+    (global_shaders().as_mut().expect("No shaders built"))
+        .insert(String::from(name), shader)
+        .expect("Shader never built")
 }
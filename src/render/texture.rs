@@ -1,7 +1,33 @@
+/// How the color channels of an image are encoded, which depends on the
+/// material slot using it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// Base color and emissive textures
+    Srgb,
+    /// Normal, metallic roughness and occlusion textures, sampled as is
+    Linear,
+}
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
-    pub sampler: &'static wgpu::Sampler,
+}
+
+/// Pixels of an image and of its mip levels, in the format of the texture
+/// they're uploaded to
+pub struct MipChain {
+    pub format: wgpu::TextureFormat,
+    /// Width, height and pixels of each level, from the full size image
+    pub levels: Vec<(u32, u32, Vec<u8>)>,
+}
+
+impl MipChain {
+    pub fn new(image: &gltf::image::Data, color_space: ColorSpace) -> Self {
+        let (format, buffer) = image_to_rgba(image, color_space);
+        let levels = generate_mip_chain(format, image.width, image.height, buffer);
+
+        Self { format, levels }
+    }
 }
 
 impl Texture {
@@ -24,27 +50,36 @@ impl Texture {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
             label: Some("Color Bind Group Layout"),
         };
 
     #[inline]
     pub fn color_texture_bind_group_layout(device: &wgpu::Device) -> &wgpu::BindGroupLayout {
-        static mut COLOR_TEXTURE_BIND_GROUP_LAYOUT: Option<wgpu::BindGroupLayout> = None;
-
-        if unsafe { COLOR_TEXTURE_BIND_GROUP_LAYOUT.is_none() } {
-            let color_bind_group_layout =
-                device.create_bind_group_layout(&Self::COLOR_TEXTURE_BIND_GROUP_LAYOUT_DESCRIPTOR);
+        static COLOR_TEXTURE_BIND_GROUP_LAYOUT: std::sync::OnceLock<wgpu::BindGroupLayout> =
+            std::sync::OnceLock::new();
 
-            unsafe {
-                COLOR_TEXTURE_BIND_GROUP_LAYOUT = Some(color_bind_group_layout);
-            }
-        }
-
-        unsafe { COLOR_TEXTURE_BIND_GROUP_LAYOUT.as_ref().unwrap() }
+        COLOR_TEXTURE_BIND_GROUP_LAYOUT.get_or_init(|| {
+            device.create_bind_group_layout(&Self::COLOR_TEXTURE_BIND_GROUP_LAYOUT_DESCRIPTOR)
+        })
     }
 
-    pub fn create_bind_group(&self, device: &wgpu::Device) -> wgpu::BindGroup {
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        sampler: &wgpu::Sampler,
+        texture_uniform: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
         let bind_group_layout = Self::color_texture_bind_group_layout(device);
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: bind_group_layout,
@@ -55,7 +90,11 @@ impl Texture {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(self.sampler),
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: texture_uniform.as_entire_binding(),
                 },
             ],
             label: Some("Color Texture Bind Group"),
@@ -63,63 +102,9 @@ impl Texture {
     }
 }
 
-const DEPTH_SAMPLER_DESCRIPTOR: wgpu::SamplerDescriptor = wgpu::SamplerDescriptor {
-    address_mode_u: wgpu::AddressMode::ClampToEdge,
-    address_mode_v: wgpu::AddressMode::ClampToEdge,
-    address_mode_w: wgpu::AddressMode::ClampToEdge,
-    mag_filter: wgpu::FilterMode::Linear,
-    min_filter: wgpu::FilterMode::Linear,
-    mipmap_filter: wgpu::FilterMode::Nearest,
-    compare: Some(wgpu::CompareFunction::LessEqual), // 5.
-    lod_min_clamp: 0.0,
-    lod_max_clamp: 100.0,
-    anisotropy_clamp: 1,
-    border_color: None,
-    label: Some("Depth texture sampler"),
-};
-
-const TEXTURE_SAMPLER_DESCRIPTOR: wgpu::SamplerDescriptor = wgpu::SamplerDescriptor {
-    address_mode_u: wgpu::AddressMode::Repeat,
-    address_mode_v: wgpu::AddressMode::Repeat,
-    address_mode_w: wgpu::AddressMode::Repeat,
-    mag_filter: wgpu::FilterMode::Linear,
-    min_filter: wgpu::FilterMode::Linear,
-    mipmap_filter: wgpu::FilterMode::Nearest,
-    compare: None,
-    lod_min_clamp: 0.0,
-    lod_max_clamp: 100.0,
-    anisotropy_clamp: 1,
-    border_color: None,
-    label: Some("Depth texture sampler"),
-};
-
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub fn get_singleton_depth_sampler(device: &wgpu::Device) -> &'static wgpu::Sampler {
-        static mut SAMPLER: Option<wgpu::Sampler> = None;
-
-        if unsafe { &SAMPLER }.is_none() {
-            unsafe {
-                SAMPLER = Some(device.create_sampler(&DEPTH_SAMPLER_DESCRIPTOR));
-            }
-        }
-
-        unsafe { SAMPLER.as_ref().unwrap() }
-    }
-
-    pub fn get_singleton_texture_sampler(device: &wgpu::Device) -> &'static wgpu::Sampler {
-        static mut SAMPLER: Option<wgpu::Sampler> = None;
-
-        if unsafe { &SAMPLER }.is_none() {
-            unsafe {
-                SAMPLER = Some(device.create_sampler(&TEXTURE_SAMPLER_DESCRIPTOR));
-            }
-        }
-
-        unsafe { SAMPLER.as_ref().unwrap() }
-    }
-
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
@@ -146,82 +131,389 @@ impl Texture {
 
         let texture = device.create_texture(&texture_descriptor);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = Self::get_singleton_depth_sampler(device);
 
-        Self {
-            texture,
-            view,
-            sampler,
-        }
+        Self { texture, view }
     }
 
-    pub fn create_texture_from_image(
+    pub fn from_mip_chain(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        image: &gltf::image::Data,
+        mip_chain: &MipChain,
     ) -> Self {
-        use gltf::image::Format::{R8G8B8, R8G8B8A8};
+        let MipChain { format, levels } = mip_chain;
+        let (width, height, _) = levels[0];
 
         #[cfg(feature = "debug_gpu")]
-        #[rustfmt::skip]
-        log::info!("Texture {}x{} : {:?}", image.width, image.height, image.format);
+        log::info!("Texture {}x{} : {:?}", width, height, format);
 
         let size = wgpu::Extent3d {
-            width: image.width,
-            height: image.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
-        assert!(
-            matches!(image.format, R8G8B8 | R8G8B8A8),
-            "Unsupported texture format"
-        );
-
-        let buffer = if image.format == R8G8B8A8 {
-            image.pixels.clone()
-        } else {
-            let mut buffer = Vec::with_capacity(image.width as usize * image.height as usize * 4);
-            for pixel in image.pixels.chunks_exact(3) {
-                buffer.extend_from_slice(pixel);
-                buffer.push(255);
-            }
-            buffer
-        };
+        let format = *format;
+        let bytes_per_pixel = format.block_size(None).expect("Not a color format");
 
         let color_texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Texture"),
             size,
-            mip_level_count: 1,
+            mip_level_count: mip_level_count(width, height),
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTextureBase {
-                texture: &color_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &buffer,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: (4 * image.width).into(),
-                rows_per_image: image.height.into(),
-            },
-            size,
-        );
+        for (mip_level, (width, height, buffer)) in (0..).zip(levels) {
+            queue.write_texture(
+                wgpu::ImageCopyTextureBase {
+                    texture: &color_texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                buffer,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: (bytes_per_pixel * width).into(),
+                    rows_per_image: (*height).into(),
+                },
+                wgpu::Extent3d {
+                    width: *width,
+                    height: *height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
 
         let texture_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let texture_sampler = Self::get_singleton_texture_sampler(device);
 
         Self {
             texture: color_texture,
             view: texture_view,
-            sampler: texture_sampler,
         }
     }
 }
+
+/// Expands `image` to four channels, the only layouts wgpu can filter for
+/// every precision.
+///
+/// 8 bits images keep their encoding, sampled through an sRGB format when
+/// `color_space` is [ColorSpace::Srgb]. 16 bits and float images go to
+/// [Rgba16Float](wgpu::TextureFormat::Rgba16Float), which has no sRGB
+/// variant, so 16 bits sRGB ones are decoded to linear here instead.
+fn image_to_rgba(
+    image: &gltf::image::Data,
+    color_space: ColorSpace,
+) -> (wgpu::TextureFormat, Vec<u8>) {
+    use gltf::image::Format;
+
+    let rgba8_format = match color_space {
+        ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+        ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+    };
+
+    let pixels = &image.pixels;
+    match image.format {
+        Format::R8G8B8A8 => (rgba8_format, pixels.clone()),
+        Format::R8G8B8 => {
+            let buffer = pixels
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u8::MAX])
+                .collect();
+            (rgba8_format, buffer)
+        }
+        // Grayscale with alpha
+        Format::R8G8 => {
+            let buffer = pixels
+                .chunks_exact(2)
+                .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+                .collect();
+            (rgba8_format, buffer)
+        }
+        Format::R8 => {
+            let buffer = pixels
+                .iter()
+                .flat_map(|&gray| [gray, gray, gray, u8::MAX])
+                .collect();
+            (rgba8_format, buffer)
+        }
+        Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16 => {
+            let channels = pixels
+                .chunks_exact(2)
+                .map(|channel| f32::from(u16::from_ne_bytes([channel[0], channel[1]])))
+                .map(|channel| channel / f32::from(u16::MAX));
+
+            let mut values = channels.collect::<Vec<_>>();
+            let channel_count = match image.format {
+                Format::R16 => 1,
+                Format::R16G16 => 2,
+                Format::R16G16B16 => 3,
+                _ => 4,
+            };
+
+            if color_space == ColorSpace::Srgb {
+                for pixel in values.chunks_exact_mut(channel_count) {
+                    // Alpha is always linear
+                    let color_count = if channel_count % 2 == 0 {
+                        channel_count - 1
+                    } else {
+                        channel_count
+                    };
+                    for color in &mut pixel[..color_count] {
+                        *color = srgb_to_linear(*color);
+                    }
+                }
+            }
+
+            (
+                wgpu::TextureFormat::Rgba16Float,
+                expand_to_rgba16_float(&values, channel_count),
+            )
+        }
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => {
+            let values = pixels
+                .chunks_exact(4)
+                .map(|value| f32::from_ne_bytes([value[0], value[1], value[2], value[3]]))
+                .collect::<Vec<_>>();
+            let channel_count = if image.format == Format::R32G32B32FLOAT {
+                3
+            } else {
+                4
+            };
+
+            (
+                wgpu::TextureFormat::Rgba16Float,
+                expand_to_rgba16_float(&values, channel_count),
+            )
+        }
+    }
+}
+
+/// Gray, gray alpha, RGB or RGBA `values` to RGBA half floats
+fn expand_to_rgba16_float(values: &[f32], channel_count: usize) -> Vec<u8> {
+    let rgba = values
+        .chunks_exact(channel_count)
+        .flat_map(|pixel| match *pixel {
+            [gray] => [gray, gray, gray, 1.0],
+            [gray, alpha] => [gray, gray, gray, alpha],
+            [red, green, blue] => [red, green, blue, 1.0],
+            [red, green, blue, alpha] => [red, green, blue, alpha],
+            _ => unreachable!("Pixels have at most 4 channels"),
+        });
+
+    let rgba = rgba.map(half::f16::from_f32).collect::<Vec<_>>();
+    bytemuck::cast_slice(&rgba).to_vec()
+}
+
+/// Levels down to a 1x1 texture
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// Box filters `pixels` down to 1x1, returning the size and pixels of every
+/// level, the first one included. Colors are averaged in linear space
+fn generate_mip_chain(
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+) -> Vec<(u32, u32, Vec<u8>)> {
+    let level_count = mip_level_count(width, height);
+    let mut mip_chain = Vec::with_capacity(level_count as usize);
+
+    let mut level = decode_rgba(format, &pixels);
+    let (mut width, mut height) = (width as usize, height as usize);
+    mip_chain.push((width as u32, height as u32, pixels));
+
+    for _ in 1..level_count {
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        let mut next_level = Vec::with_capacity(next_width * next_height);
+
+        for y in 0..next_height {
+            // Odd sizes drop their last row or column
+            let rows = [(2 * y).min(height - 1), (2 * y + 1).min(height - 1)];
+            for x in 0..next_width {
+                let columns = [(2 * x).min(width - 1), (2 * x + 1).min(width - 1)];
+
+                let sum = rows
+                    .iter()
+                    .flat_map(|row| columns.iter().map(move |column| row * width + column))
+                    .map(|index| level[index])
+                    .fold(glam::Vec4::ZERO, |sum, pixel| sum + pixel);
+                next_level.push(sum / 4.0);
+            }
+        }
+
+        (level, width, height) = (next_level, next_width, next_height);
+        let pixels = encode_rgba(format, &level);
+        mip_chain.push((width as u32, height as u32, pixels));
+    }
+
+    mip_chain
+}
+
+fn decode_rgba(format: wgpu::TextureFormat, pixels: &[u8]) -> Vec<glam::Vec4> {
+    match format {
+        wgpu::TextureFormat::Rgba8UnormSrgb => pixels
+            .chunks_exact(4)
+            .map(|pixel| {
+                let [red, green, blue, alpha] = [0, 1, 2, 3].map(|i| f32::from(pixel[i]) / 255.0);
+                glam::Vec4::new(
+                    srgb_to_linear(red),
+                    srgb_to_linear(green),
+                    srgb_to_linear(blue),
+                    alpha,
+                )
+            })
+            .collect(),
+        wgpu::TextureFormat::Rgba8Unorm => pixels
+            .iter()
+            .map(|&channel| f32::from(channel) / 255.0)
+            .collect::<Vec<_>>()
+            .chunks_exact(4)
+            .map(glam::Vec4::from_slice)
+            .collect(),
+        wgpu::TextureFormat::Rgba16Float => bytemuck::pod_collect_to_vec::<u8, half::f16>(pixels)
+            .chunks_exact(4)
+            .map(|pixel| glam::Vec4::from_array([0, 1, 2, 3].map(|i| pixel[i].to_f32())))
+            .collect(),
+        _ => unreachable!("Not a color texture format: {:?}", format),
+    }
+}
+
+fn encode_rgba(format: wgpu::TextureFormat, pixels: &[glam::Vec4]) -> Vec<u8> {
+    match format {
+        wgpu::TextureFormat::Rgba8UnormSrgb => pixels
+            .iter()
+            .flat_map(|pixel| {
+                let color = [pixel.x, pixel.y, pixel.z].map(linear_to_srgb);
+                [color[0], color[1], color[2], pixel.w]
+                    .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+            })
+            .collect(),
+        wgpu::TextureFormat::Rgba8Unorm => pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_array())
+            .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect(),
+        wgpu::TextureFormat::Rgba16Float => {
+            let pixels = pixels
+                .iter()
+                .flat_map(|pixel| pixel.to_array().map(half::f16::from_f32))
+                .collect::<Vec<_>>();
+            bytemuck::cast_slice(&pixels).to_vec()
+        }
+        _ => unreachable!("Not a color texture format: {:?}", format),
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gltf::image::{Data, Format};
+
+    use super::*;
+
+    fn image(format: Format, width: u32, height: u32, pixels: Vec<u8>) -> Data {
+        Data {
+            pixels,
+            format,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn eight_bits_images_expand_to_rgba8() {
+        let rgb = image(Format::R8G8B8, 2, 1, vec![1, 2, 3, 4, 5, 6]);
+        let (format, pixels) = image_to_rgba(&rgb, ColorSpace::Srgb);
+        assert_eq!(format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(pixels, [1, 2, 3, 255, 4, 5, 6, 255]);
+
+        let gray_alpha = image(Format::R8G8, 1, 1, vec![7, 8]);
+        let (format, pixels) = image_to_rgba(&gray_alpha, ColorSpace::Linear);
+        assert_eq!(format, wgpu::TextureFormat::Rgba8Unorm);
+        assert_eq!(pixels, [7, 7, 7, 8]);
+
+        let gray = image(Format::R8, 1, 1, vec![9]);
+        let (_, pixels) = image_to_rgba(&gray, ColorSpace::Linear);
+        assert_eq!(pixels, [9, 9, 9, 255]);
+    }
+
+    #[test]
+    fn wide_images_expand_to_rgba16_float() {
+        let half_pixels = |pixels: &[u8]| {
+            bytemuck::pod_collect_to_vec::<u8, half::f16>(pixels)
+                .into_iter()
+                .map(half::f16::to_f32)
+                .collect::<Vec<_>>()
+        };
+
+        let gray = image(Format::R16, 1, 1, u16::MAX.to_ne_bytes().to_vec());
+        let (format, pixels) = image_to_rgba(&gray, ColorSpace::Linear);
+        assert_eq!(format, wgpu::TextureFormat::Rgba16Float);
+        assert_eq!(half_pixels(&pixels), [1.0; 4]);
+
+        // sRGB colors are decoded, alpha stays linear
+        let half_gray = 0.5f32;
+        let gray_alpha = [32768u16, 32768].map(u16::to_ne_bytes).concat();
+        let (_, pixels) = image_to_rgba(&image(Format::R16G16, 1, 1, gray_alpha), ColorSpace::Srgb);
+        let pixels = half_pixels(&pixels);
+        let linear = srgb_to_linear(half_gray);
+        assert!((pixels[0] - linear).abs() < 1e-3);
+        assert!((pixels[3] - half_gray).abs() < 1e-3);
+
+        let rgb = [0.25f32, 0.5, 2.0].map(f32::to_ne_bytes).concat();
+        let (format, pixels) = image_to_rgba(
+            &image(Format::R32G32B32FLOAT, 1, 1, rgb),
+            ColorSpace::Linear,
+        );
+        assert_eq!(format, wgpu::TextureFormat::Rgba16Float);
+        assert_eq!(half_pixels(&pixels), [0.25, 0.5, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn mip_chains_go_down_to_one_pixel() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 1), 9);
+        assert_eq!(mip_level_count(5, 3), 3);
+
+        let format = wgpu::TextureFormat::Rgba16Float;
+        let levels = generate_mip_chain(format, 5, 3, vec![0; 5 * 3 * 8]);
+        let sizes = (levels.iter())
+            .map(|(width, height, pixels)| (*width, *height, pixels.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [(5, 3, 5 * 3 * 8), (2, 1, 2 * 8), (1, 1, 8)]);
+    }
+
+    #[test]
+    fn mip_levels_average_in_linear_space() {
+        let checker = [[0, 0, 0, 255], [255, 255, 255, 255]];
+        let pixels = [checker[0], checker[1], checker[1], checker[0]].concat();
+
+        let linear = generate_mip_chain(wgpu::TextureFormat::Rgba8Unorm, 2, 2, pixels.clone());
+        assert_eq!(linear[1], (1, 1, vec![128, 128, 128, 255]));
+
+        // Half the light of white, not the middle sRGB value
+        let srgb = generate_mip_chain(wgpu::TextureFormat::Rgba8UnormSrgb, 2, 2, pixels);
+        assert_eq!(srgb[1], (1, 1, vec![188, 188, 188, 255]));
+    }
+}
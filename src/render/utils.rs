@@ -22,15 +22,15 @@ pub fn pretty_gltf_tree<P: AsRef<std::path::Path>>(path: P) {
         }
     }
 
-    static mut INDENT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    static INDENT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
     macro_rules! indent {
         () => {
-            "|    ".repeat(unsafe { INDENT.load(Relaxed) })
+            "|    ".repeat(INDENT.load(Relaxed))
         };
     }
 
     fn handle_mesh(mesh: Option<gltf::Mesh>) {
-        unsafe { INDENT.fetch_add(1, Relaxed) };
+        INDENT.fetch_add(1, Relaxed);
         if let Some(mesh) = mesh {
             print_mesh(&mesh);
             for primitive in mesh.primitives() {
@@ -39,11 +39,11 @@ pub fn pretty_gltf_tree<P: AsRef<std::path::Path>>(path: P) {
         } else {
             println!("{}Mesh: None", indent!());
         }
-        unsafe { INDENT.fetch_sub(1, Relaxed) };
+        INDENT.fetch_sub(1, Relaxed);
     }
 
     fn handle_node(node: &gltf::Node, node_parent: &HashMap<usize, usize>) {
-        unsafe { INDENT.fetch_add(1, Relaxed) };
+        INDENT.fetch_add(1, Relaxed);
         print_node(&node, &node_parent);
 
         for child in node.children() {
@@ -51,7 +51,7 @@ pub fn pretty_gltf_tree<P: AsRef<std::path::Path>>(path: P) {
         }
         handle_mesh(node.mesh());
 
-        unsafe { INDENT.fetch_sub(1, Relaxed) };
+        INDENT.fetch_sub(1, Relaxed);
     }
 
     fn print_node(node: &gltf::Node, node_parent: &HashMap<usize, usize>) {
@@ -90,7 +90,7 @@ pub fn pretty_gltf_tree<P: AsRef<std::path::Path>>(path: P) {
     }
 
     for scene in gltf.scenes() {
-        unsafe { INDENT.store(0, Relaxed) };
+        INDENT.store(0, Relaxed);
 
         let name = scene.name().unwrap_or("None");
         let children_count = scene.nodes().count();